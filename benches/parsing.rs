@@ -0,0 +1,72 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rust_aliaser::{
+    diff::{diff_lexer::DiffLexer, diff_parser::DiffParser},
+    pretty_medium::{lexer::Lexer, parser::Parser},
+};
+
+// a few thousand file diffs with a mix of added, removed and context lines
+fn synthetic_diff(files: usize) -> Vec<u8> {
+    let mut out = String::new();
+    for f in 0..files {
+        out.push_str(&format!(
+            "diff --git a/src/file_{f}.rs b/src/file_{f}.rs\n\
+             index 318bd87..0f3e0a1 100644\n\
+             --- a/src/file_{f}.rs\n\
+             +++ b/src/file_{f}.rs\n"
+        ));
+        for h in 0..4 {
+            out.push_str(&format!(
+                "@@ -{0},12 +{0},12 @@ fn hunk_{h}() {{\n",
+                h * 40 + 1
+            ));
+            for l in 0..12 {
+                let prefix = match l % 4 {
+                    0 => '-',
+                    1 => '+',
+                    _ => ' ',
+                };
+                out.push_str(&format!(
+                    "{prefix}\tlet value_{l} = compute(\"{f}\", {h}, {l}).unwrap_or_default();\n"
+                ));
+            }
+        }
+    }
+    out.into_bytes()
+}
+
+fn synthetic_log(commits: usize) -> Vec<u8> {
+    let mut out = String::new();
+    for c in 0..commits {
+        out.push_str(&format!(
+            "commit {c:040x}\n\
+             Author: Some Author <author@example.com>\n\
+             Date:   Sat Nov 25 15:58:03 2023 -0500\n\n    \
+             feat: commit number {c}\n\n    \
+             a longer body explaining what changed and why it changed\n\n"
+        ));
+    }
+    out.into_bytes()
+}
+
+fn diff_parsing(c: &mut Criterion) {
+    let input = synthetic_diff(2_000);
+    let mut group = c.benchmark_group("diff");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("parse_program", |b| {
+        b.iter(|| DiffParser::new(DiffLexer::new(black_box(&input))).parse_program())
+    });
+    group.finish();
+}
+
+fn log_parsing(c: &mut Criterion) {
+    let input = synthetic_log(10_000);
+    let mut group = c.benchmark_group("pretty_medium");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("parse_program", |b| {
+        b.iter(|| Parser::new(Lexer::new(black_box(&input))).parse_program())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, diff_parsing, log_parsing);
+criterion_main!(benches);
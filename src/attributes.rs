@@ -0,0 +1,291 @@
+//! Files that `.gitattributes` marks as generated, vendored or binary, which
+//! aren't searched by default.
+//!
+//! Attributes are read at the revision a file is searched in, from the
+//! `.gitattributes` of the repository root and of every directory above the
+//! file, deeper files and later lines taking precedence. Only the attributes
+//! needed for that are interpreted: `linguist-generated`, `linguist-vendored`,
+//! `diff` and the `binary` macro.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use globset::{GlobBuilder, GlobMatcher};
+
+use crate::paths::anchored;
+
+// the state of an attribute, as far as searching is concerned
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum State {
+    // no line mentions the attribute
+    #[default]
+    Unmentioned,
+    // `!attr`, which undoes what earlier lines said
+    Unspecified,
+    Set,
+    Unset,
+}
+
+impl State {
+    fn or(self, earlier: State) -> State {
+        if self == State::Unmentioned {
+            earlier
+        } else {
+            self
+        }
+    }
+}
+
+// what a file's attributes say about searching it
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Marks {
+    generated: State,
+    vendored: State,
+    diff: State,
+}
+
+impl Marks {
+    fn merge(&mut self, other: Marks) {
+        self.generated = other.generated.or(self.generated);
+        self.vendored = other.vendored.or(self.vendored);
+        self.diff = other.diff.or(self.diff);
+    }
+
+    fn is_excluded(&self) -> bool {
+        self.generated == State::Set || self.vendored == State::Set || self.diff == State::Unset
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    glob: GlobMatcher,
+    marks: Marks,
+}
+
+// the rules of a .gitattributes file that mention any of the attributes in `Marks`
+fn parse(content: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    for line in content.lines().map(str::trim) {
+        let mut fields = line.split_whitespace();
+        let Some(pattern) = fields.next() else {
+            continue;
+        };
+        // comments, macro definitions and directories, which attributes don't apply to
+        if pattern.starts_with('#') || pattern.starts_with("[attr]") || pattern.ends_with('/') {
+            continue;
+        }
+        let mut marks = Marks::default();
+        for attribute in fields {
+            let (name, state) = if let Some(name) = attribute.strip_prefix('-') {
+                (name, State::Unset)
+            } else if let Some(name) = attribute.strip_prefix('!') {
+                (name, State::Unspecified)
+            } else if let Some((name, value)) = attribute.split_once('=') {
+                // `diff=driver` diffs with a driver, which is still text
+                if name == "diff" || value != "false" {
+                    (name, State::Set)
+                } else {
+                    (name, State::Unset)
+                }
+            } else {
+                (attribute, State::Set)
+            };
+            match name {
+                "linguist-generated" => marks.generated = state,
+                "linguist-vendored" => marks.vendored = state,
+                "diff" => marks.diff = state,
+                "binary" if state == State::Set => marks.diff = State::Unset,
+                _ => {}
+            }
+        }
+        if marks == Marks::default() {
+            continue;
+        }
+        let glob = GlobBuilder::new(&anchored(pattern))
+            .literal_separator(true)
+            .build();
+        // git ignores patterns it can't parse, and so do we
+        if let Ok(glob) = glob {
+            rules.push(Rule {
+                glob: glob.compile_matcher(),
+                marks,
+            });
+        }
+    }
+    rules
+}
+
+fn apply(rules: &[Rule], relative_path: &str, marks: &mut Marks) {
+    for rule in rules.iter().filter(|r| r.glob.is_match(relative_path)) {
+        marks.merge(rule.marks);
+    }
+}
+
+#[derive(Debug)]
+struct Batch {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Drop for Batch {
+    fn drop(&mut self) {
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
+
+/// Reads `.gitattributes` files from any revision of a repository with a
+/// single `git cat-file --batch` process, shared by every thread.
+#[derive(Debug)]
+pub struct Attributes {
+    batch: Mutex<Batch>,
+    // parsed files by the hash of their blob, most revisions share them
+    parsed: Mutex<HashMap<String, Arc<Vec<Rule>>>>,
+}
+
+impl Attributes {
+    pub fn open(target_dir: Option<&str>) -> Result<Self> {
+        let mut com = Command::new("git");
+        com.args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        if let Some(t) = target_dir {
+            com.current_dir(t);
+        }
+        let mut child = com.spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        Ok(Self {
+            batch: Mutex::new(Batch {
+                child,
+                stdin,
+                stdout,
+            }),
+            parsed: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Whether `path`, relative to the repository root, is marked generated,
+    /// vendored or not to be diffed at `rev`.
+    pub fn is_excluded(&self, rev: &str, path: &str) -> Result<bool> {
+        let mut marks = Marks::default();
+        let mut dir_len = 0;
+        loop {
+            let (dir, relative_path) = path.split_at(dir_len);
+            if let Some(rules) = self.rules(&format!("{rev}:{dir}.gitattributes"))? {
+                apply(&rules, relative_path, &mut marks);
+            }
+            match relative_path.find('/') {
+                Some(end) => dir_len += end + 1,
+                None => break,
+            }
+        }
+        Ok(marks.is_excluded())
+    }
+
+    // the rules of the blob `object`, `None` if there's no such blob
+    fn rules(&self, object: &str) -> Result<Option<Arc<Vec<Rule>>>> {
+        let mut batch = self.batch.lock().expect("cat-file isn't poisoned");
+        writeln!(batch.stdin, "{object}")?;
+        batch.stdin.flush()?;
+        let mut header = String::new();
+        if batch.stdout.read_line(&mut header)? == 0 {
+            return Err(anyhow!("git cat-file exited"));
+        }
+        let header = header.trim_end();
+        if header.ends_with(" missing") || header.ends_with(" ambiguous") {
+            return Ok(None);
+        }
+        let (hash, kind, size) = match header.split(' ').collect::<Vec<_>>()[..] {
+            [hash, kind, size] => (hash.to_string(), kind == "blob", size.parse::<usize>()?),
+            _ => return Err(anyhow!("unexpected git cat-file output: {header}")),
+        };
+        // the content is followed by a newline
+        let mut content = vec![0; size + 1];
+        batch.stdout.read_exact(&mut content)?;
+        drop(batch);
+        if !kind {
+            return Ok(None);
+        }
+
+        let mut parsed = self.parsed.lock().expect("cache isn't poisoned");
+        let rules = parsed.entry(hash).or_insert_with(|| {
+            content.pop();
+            Arc::new(parse(&String::from_utf8_lossy(&content)))
+        });
+        Ok(Some(rules.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, parse, Marks};
+
+    fn is_excluded(content: &str, path: &str) -> bool {
+        let mut marks = Marks::default();
+        apply(&parse(content), path, &mut marks);
+        marks.is_excluded()
+    }
+
+    #[test]
+    fn marked_files() {
+        let content = "\
+# generated code
+*.pb.go linguist-generated
+dist/** linguist-vendored=true
+*.bin binary
+*.svg -diff
+*.rs diff=rust
+";
+
+        assert!(is_excluded(content, "api/service.pb.go"));
+        assert!(is_excluded(content, "dist/app.min.js"));
+        assert!(is_excluded(content, "assets/logo.svg"));
+        assert!(is_excluded(content, "data.bin"));
+        assert!(!is_excluded(content, "src/dist/app.js"));
+        assert!(!is_excluded(content, "src/main.rs"));
+        assert!(!is_excluded(content, "service.go"));
+    }
+
+    #[test]
+    fn later_lines_take_precedence() {
+        let content = "\
+vendor/** linguist-vendored
+vendor/ours/** -linguist-vendored
+*.lock linguist-generated
+Cargo.lock !linguist-generated
+";
+
+        assert!(is_excluded(content, "vendor/lib/a.c"));
+        assert!(!is_excluded(content, "vendor/ours/a.c"));
+        assert!(is_excluded(content, "yarn.lock"));
+        assert!(!is_excluded(content, "Cargo.lock"));
+    }
+
+    #[test]
+    fn unspecified_is_not_unset() {
+        let content = "\
+*.svg -diff
+icons/*.svg !diff
+*.dat !diff
+";
+
+        assert!(is_excluded(content, "assets/logo.svg"));
+        assert!(!is_excluded(content, "icons/logo.svg"));
+        assert!(!is_excluded(content, "data.dat"));
+    }
+
+    #[test]
+    fn unrelated_rules_are_ignored() {
+        let rules = parse("[attr]generated linguist-generated\n*.txt text eol=lf\nbuild/ binary\n");
+
+        assert!(rules.is_empty());
+        assert!(!is_excluded("*.txt linguist-generated=false\n", "a.txt"));
+    }
+}
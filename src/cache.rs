@@ -0,0 +1,391 @@
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::diff::diff_ast::{Chunk, Content, ContentType, Statement};
+
+const CACHE_HEADER: &[u8] = b"git_grep diff cache v3\n";
+
+/// The directory `git_grep` keeps its data in, `<git dir>/git_grep`. It is
+/// shared by every worktree of the repository.
+pub fn git_grep_dir(target_dir: Option<&str>) -> Result<PathBuf> {
+    let mut com = Command::new("git");
+    com.args(["rev-parse", "--git-common-dir"]);
+    if let Some(t) = target_dir {
+        com.current_dir(t);
+    }
+    let out = com.output()?;
+    if !out.status.success() {
+        return Err(anyhow!("not a git repository"));
+    }
+    let git_dir = PathBuf::from(String::from_utf8(out.stdout)?.trim());
+    let git_dir = match target_dir {
+        Some(t) if git_dir.is_relative() => Path::new(t).join(git_dir),
+        _ => git_dir,
+    };
+    Ok(git_dir.join("git_grep"))
+}
+
+/// Everything that changes the output of `git diff` for a commit.
+#[derive(Debug, Clone, Copy)]
+pub struct DiffKey<'a> {
+    pub parent: &'a str,
+    pub commit: &'a str,
+    pub context: usize,
+    pub pathspecs: &'a [String],
+}
+
+// a hash that stays the same across builds, unlike `DefaultHasher`
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Parsed diffs stored under `<git dir>/git_grep/cache`, keyed by the object
+/// IDs being diffed. Since those never change, an entry never goes stale.
+///
+/// Entries are a compact line store: a `F\t<a_file>\t<b_file>` line per file,
+/// followed by an `H\t<line>` line per extended header line, a
+/// `@\t<removed start>\t<removed changes>\t<added start>\t<added changes>\t<heading>`
+/// line per chunk, and the chunk's lines, each prefixed with `+`, `-` or a space.
+/// A line without a newline at the end of the file is followed by a `\` line.
+#[derive(Debug, Clone)]
+pub struct DiffCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl DiffCache {
+    /// Finds the cache directory of the repository at `target_dir` (or the
+    /// current directory).
+    pub fn open(target_dir: Option<&str>, max_bytes: u64) -> Result<Self> {
+        Ok(Self::at(git_grep_dir(target_dir)?.join("cache"), max_bytes))
+    }
+
+    pub fn at(dir: PathBuf, max_bytes: u64) -> Self {
+        Self { dir, max_bytes }
+    }
+
+    fn entry_path(&self, key: &DiffKey) -> PathBuf {
+        let DiffKey {
+            parent,
+            commit,
+            context,
+            pathspecs,
+        } = key;
+        let fanout = commit.get(..2).unwrap_or("00");
+        let mut name = format!("{commit}-{parent}-U{context}");
+        if !pathspecs.is_empty() {
+            name.push_str(&format!("-p{:016x}", fnv1a(&pathspecs.join("\0"))));
+        }
+        self.dir.join(fanout).join(name)
+    }
+
+    /// Returns the raw entry for a diff, use `statements` to read it.
+    pub fn read(&self, key: &DiffKey) -> Option<Vec<u8>> {
+        let path = self.entry_path(key);
+        let data = fs::read(&path).ok()?;
+        if !data.starts_with(CACHE_HEADER) {
+            return None;
+        }
+        // bump the modification time so pruning removes the least recently used entries
+        if let Ok(f) = File::options().append(true).open(&path) {
+            _ = f.set_modified(SystemTime::now());
+        }
+        Some(data)
+    }
+
+    pub fn writer(&self, key: &DiffKey) -> Result<CacheWriter> {
+        let path = self.entry_path(key);
+        fs::create_dir_all(path.parent().expect("entries are in a fanout directory"))?;
+        let tmp_path = path.with_extension(format!("tmp{}", std::process::id()));
+
+        let mut file = BufWriter::new(File::create(&tmp_path)?);
+        file.write_all(CACHE_HEADER)?;
+        Ok(CacheWriter {
+            file,
+            tmp_path,
+            path,
+        })
+    }
+
+    /// Removes the least recently used entries until the cache fits in its size limit.
+    pub fn prune(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+        let Ok(fanouts) = fs::read_dir(&self.dir) else {
+            return Ok(());
+        };
+        for fanout in fanouts {
+            for entry in fs::read_dir(fanout?.path())? {
+                let entry = entry?;
+                let meta = entry.metadata()?;
+                total += meta.len();
+                entries.push((meta.modified()?, meta.len(), entry.path()));
+            }
+        }
+
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Writes an entry to a temporary file, which only replaces the entry once
+/// `finish` is called. Dropping the writer discards it.
+pub struct CacheWriter {
+    file: BufWriter<File>,
+    tmp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl CacheWriter {
+    pub fn write_statement(&mut self, statement: &Statement) -> Result<()> {
+        writeln!(self.file, "F\t{}\t{}", statement.a_file, statement.b_file)?;
+        for line in &statement.header {
+            writeln!(self.file, "H\t{line}")?;
+        }
+        for chunk in &statement.chunks {
+            writeln!(
+                self.file,
+                "@\t{}\t{}\t{}\t{}\t{}",
+                chunk.removed_start,
+                chunk.removed_changes,
+                chunk.added_start,
+                chunk.added_changes,
+                chunk.heading
+            )?;
+            for content in &chunk.content {
+                let prefix = match content.c_type {
+                    ContentType::Add => b'+',
+                    ContentType::Remove => b'-',
+                    ContentType::Neutral => b' ',
+                };
+                self.file.write_all(&[prefix])?;
+                self.file.write_all(content.line_data.as_bytes())?;
+                self.file.write_all(b"\n")?;
+                if content.no_newline {
+                    self.file.write_all(b"\\\n")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.file.flush()?;
+        fs::rename(&self.tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        // a no-op once the entry has been renamed into place
+        _ = fs::remove_file(&self.tmp_path);
+    }
+}
+
+/// Reads the statements of an entry returned by `DiffCache::read`, borrowing
+/// every line from it.
+pub fn statements(entry: &[u8]) -> impl Iterator<Item = Result<Statement<'_>, String>> {
+    let mut rest = entry.strip_prefix(CACHE_HEADER).unwrap_or_default();
+    std::iter::from_fn(move || {
+        let header = next_line(&mut rest)?;
+        let Some(files) = header.strip_prefix(b"F\t") else {
+            return Some(Err("cache entry is corrupt".to_string()));
+        };
+        // everything in an entry was written from valid UTF-8
+        let Some((a_file, b_file)) = std::str::from_utf8(files)
+            .ok()
+            .and_then(|files| files.split_once('\t'))
+        else {
+            return Some(Err("cache entry is corrupt".to_string()));
+        };
+
+        let mut extended = Vec::new();
+        let mut chunks: Vec<Chunk> = Vec::new();
+        while !rest.is_empty() && !rest.starts_with(b"F\t") {
+            let line = next_line(&mut rest)?;
+            if let Some(line) = line.strip_prefix(b"H\t") {
+                extended.push(String::from_utf8_lossy(line));
+                continue;
+            }
+            if let Some(header) = line.strip_prefix(b"@\t") {
+                let Some(chunk) = parse_chunk_header(header) else {
+                    return Some(Err("cache entry is corrupt".to_string()));
+                };
+                chunks.push(chunk);
+                continue;
+            }
+            let Some(chunk) = chunks.last_mut() else {
+                return Some(Err("cache entry is corrupt".to_string()));
+            };
+            let c_type = match line.first() {
+                Some(b'+') => ContentType::Add,
+                Some(b'-') => ContentType::Remove,
+                Some(b'\\') => {
+                    if let Some(last) = chunk.content.last_mut() {
+                        last.no_newline = true;
+                    }
+                    continue;
+                }
+                _ => ContentType::Neutral,
+            };
+            chunk.content.push(Content {
+                line_data: String::from_utf8_lossy(line.get(1..).unwrap_or_default()),
+                c_type,
+                no_newline: false,
+            });
+        }
+
+        Some(Ok(Statement {
+            a_file: Cow::Borrowed(a_file),
+            b_file: Cow::Borrowed(b_file),
+            header: extended,
+            chunks,
+        }))
+    })
+}
+
+fn parse_chunk_header(header: &[u8]) -> Option<Chunk<'_>> {
+    // the heading is last, it can contain tabs
+    let mut fields = std::str::from_utf8(header).ok()?.splitn(5, '\t');
+    let mut number = || fields.next()?.parse().ok();
+    Some(Chunk {
+        removed_start: number()?,
+        removed_changes: number()?,
+        added_start: number()?,
+        added_changes: number()?,
+        heading: Cow::Borrowed(fields.next()?),
+        content: Vec::new(),
+    })
+}
+
+fn next_line<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+    if rest.is_empty() {
+        return None;
+    }
+    let (line, next) = match memchr::memchr(b'\n', rest) {
+        Some(end) => (&rest[..end], &rest[end + 1..]),
+        None => (*rest, &rest[rest.len()..]),
+    };
+    *rest = next;
+    Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread, time::Duration};
+
+    use super::{statements, DiffCache, DiffKey};
+    use crate::diff::{diff_lexer::DiffLexer, diff_parser::DiffParser};
+
+    const DIFF: &str = r#"diff --git a/src/ast.rs b/src/ast.rs
+index 318bd87..0f3e0a1 100644
+--- a/src/ast.rs
++++ b/src/ast.rs
+@@ -1,3 +1,3 @@ mod ast {
+-enum Ast {
++enum Tree {
+ 	Leaf,
+ }
+\ No newline at end of file
+diff --git a/empty b/empty
+new file mode 100644
+index 0000000..e69de29
+"#;
+
+    fn key(commit: &str, context: usize) -> DiffKey<'_> {
+        DiffKey {
+            parent: "parent",
+            commit,
+            context,
+            pathspecs: &[],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiffCache::at(dir.path().to_path_buf(), u64::MAX);
+        let program = DiffParser::new(DiffLexer::new(DIFF.as_bytes())).parse_program();
+
+        assert!(cache.read(&key("commit", 0)).is_none());
+        let mut writer = cache.writer(&key("commit", 0)).unwrap();
+        for statement in &program.statements {
+            writer.write_statement(statement).unwrap();
+        }
+        writer.finish().unwrap();
+
+        assert!(cache.read(&key("commit", 3)).is_none());
+        let pathspecs = ["src/".to_string()];
+        assert!(cache
+            .read(&DiffKey {
+                pathspecs: &pathspecs,
+                ..key("commit", 0)
+            })
+            .is_none());
+        let entry = cache.read(&key("commit", 0)).unwrap();
+        let cached = statements(&entry).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(cached, program.statements);
+    }
+
+    #[test]
+    fn unfinished_entries_are_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiffCache::at(dir.path().to_path_buf(), u64::MAX);
+
+        drop(cache.writer(&key("commit", 0)).unwrap());
+
+        assert!(cache.read(&key("commit", 0)).is_none());
+        assert_eq!(fs::read_dir(dir.path().join("co")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn prune_removes_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = DiffParser::new(DiffLexer::new(DIFF.as_bytes())).parse_program();
+        let unlimited = DiffCache::at(dir.path().to_path_buf(), u64::MAX);
+        for commit in ["aaaa", "bbbb", "cccc"] {
+            let mut writer = unlimited.writer(&key(commit, 0)).unwrap();
+            writer.write_statement(&program.statements[0]).unwrap();
+            writer.finish().unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+        // reading an entry marks it as recently used
+        unlimited.read(&key("aaaa", 0)).unwrap();
+
+        let entry_size = fs::metadata(dir.path().join("bb/bbbb-parent-U0"))
+            .unwrap()
+            .len();
+        DiffCache::at(dir.path().to_path_buf(), entry_size * 2)
+            .prune()
+            .unwrap();
+
+        assert!(unlimited.read(&key("aaaa", 0)).is_some());
+        assert!(unlimited.read(&key("bbbb", 0)).is_none());
+        assert!(unlimited.read(&key("cccc", 0)).is_some());
+    }
+}
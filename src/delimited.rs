@@ -0,0 +1,213 @@
+//! CSV and TSV output, one row per matched line.
+
+use std::io::{self, Write};
+
+use crate::{
+    diff::diff_ast::ContentType,
+    matcher::{CommitMatcher, DiffLine, FileMatches},
+    paths::strip_prefix,
+    sink::{Sink, Summary},
+};
+
+/// A column of the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    /// the hash, or `staged` or `worktree` for uncommitted changes
+    Commit,
+    Parent,
+    Author,
+    Date,
+    /// the commit's notes, on a single line
+    Notes,
+    /// the path in the revision that has the line
+    File,
+    OldLine,
+    NewLine,
+    /// `+` or `-`
+    Side,
+    /// the patterns found in the line
+    Pattern,
+    Text,
+}
+
+impl Column {
+    pub const ALL: [Column; 11] = [
+        Column::Commit,
+        Column::Parent,
+        Column::Author,
+        Column::Date,
+        Column::Notes,
+        Column::File,
+        Column::OldLine,
+        Column::NewLine,
+        Column::Side,
+        Column::Pattern,
+        Column::Text,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Commit => "commit",
+            Column::Parent => "parent",
+            Column::Author => "author",
+            Column::Date => "date",
+            Column::Notes => "notes",
+            Column::File => "file",
+            Column::OldLine => "old_line",
+            Column::NewLine => "new_line",
+            Column::Side => "side",
+            Column::Pattern => "pattern",
+            Column::Text => "text",
+        }
+    }
+}
+
+/// Writes a row per matched line, quoting fields as needed.
+pub struct DelimitedSink<W: Write> {
+    out: W,
+    delimiter: u8,
+    columns: Vec<Column>,
+    header: bool,
+    patterns: Vec<String>,
+    // the commit and file the following lines belong to
+    hash: String,
+    parent: String,
+    author: String,
+    date: String,
+    notes: String,
+    old_path: String,
+    new_path: String,
+}
+
+impl<W: Write> DelimitedSink<W> {
+    /// Every column, with a header row.
+    pub fn new(out: W, delimiter: u8) -> Self {
+        Self {
+            out,
+            delimiter,
+            columns: Column::ALL.to_vec(),
+            header: true,
+            patterns: Vec::new(),
+            hash: String::new(),
+            parent: String::new(),
+            author: String::new(),
+            date: String::new(),
+            notes: String::new(),
+            old_path: String::new(),
+            new_path: String::new(),
+        }
+    }
+
+    pub fn csv(out: W) -> Self {
+        Self::new(out, b',')
+    }
+
+    pub fn tsv(out: W) -> Self {
+        Self::new(out, b'\t')
+    }
+
+    pub fn columns(mut self, columns: Vec<Column>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_row<'a>(&mut self, fields: impl IntoIterator<Item = &'a str>) -> io::Result<()> {
+        // formats one row at a time, so `out` doesn't have to be wrapped
+        let mut row = csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .from_writer(Vec::new());
+        row.write_record(fields)?;
+        let row = row.into_inner().map_err(|e| e.into_error())?;
+        self.out.write_all(&row)
+    }
+
+    fn field(&self, column: Column, line: &DiffLine) -> String {
+        let removed = *line.side() == ContentType::Remove;
+        let number = |n: Option<usize>| n.map(|n| n.to_string()).unwrap_or_default();
+        match column {
+            Column::Commit => self.hash.clone(),
+            Column::Parent => self.parent.clone(),
+            Column::Author => self.author.clone(),
+            Column::Date => self.date.clone(),
+            Column::Notes => self.notes.clone(),
+            Column::File if removed => self.old_path.clone(),
+            Column::File => self.new_path.clone(),
+            Column::OldLine => number(line.old_line()),
+            Column::NewLine => number(line.new_line()),
+            Column::Side => line.side().to_string(),
+            Column::Pattern => self.line_patterns(line).join(", "),
+            Column::Text => line.text().to_string(),
+        }
+    }
+
+    // in the order they're found, a line without spans matched the only pattern
+    fn line_patterns(&self, line: &DiffLine) -> Vec<&str> {
+        if line.spans().is_empty() && self.patterns.len() == 1 {
+            return vec![&self.patterns[0]];
+        }
+        let mut found: Vec<&str> = Vec::new();
+        for span in line.spans() {
+            let pattern = self.patterns.get(span.pattern).map_or("", String::as_str);
+            if !found.contains(&pattern) {
+                found.push(pattern);
+            }
+        }
+        found
+    }
+}
+
+impl<W: Write> Sink for DelimitedSink<W> {
+    fn begin(&mut self, patterns: &[String]) -> io::Result<()> {
+        self.patterns = patterns.to_vec();
+        if !self.header {
+            return Ok(());
+        }
+        let names: Vec<&str> = self.columns.iter().map(Column::name).collect();
+        self.write_row(names)
+    }
+
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()> {
+        self.hash = commit.name().to_string();
+        self.parent = commit.parent_hash().to_string();
+        self.author.clone_from(&commit.commit().author);
+        self.date.clone_from(&commit.commit().date);
+        self.notes = commit.commit().notes();
+        Ok(())
+    }
+
+    fn file(&mut self, file: &FileMatches) -> io::Result<()> {
+        self.old_path = strip_prefix(file.file_a(), "a/").to_string();
+        self.new_path = strip_prefix(file.file_b(), "b/").to_string();
+        Ok(())
+    }
+
+    fn match_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        let fields: Vec<String> = self
+            .columns
+            .iter()
+            .map(|&column| self.field(column, line))
+            .collect();
+        self.write_row(fields.iter().map(String::as_str))
+    }
+
+    fn context_line(&mut self, _line: &DiffLine) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end_commit(&mut self, _commit: &CommitMatcher) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn end(&mut self, _summary: &Summary) -> io::Result<()> {
+        self.out.flush()
+    }
+}
@@ -1,70 +1,133 @@
-use std::sync::Arc;
+use std::{borrow::Cow, fmt::Display};
 
-use crate::Options;
-
-#[derive(Debug, PartialEq)]
-pub struct Program {
-    pub statements: Vec<Statement>,
+#[derive(Debug, PartialEq, Default)]
+pub struct Program<'a> {
+    pub statements: Vec<Statement<'a>>,
     pub errors: Vec<String>,
 }
 
-impl Program {
+impl Program<'_> {
     pub fn new() -> Self {
-        Self {
-            statements: Vec::new(),
-            errors: Vec::new(),
-        }
+        Self::default()
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub enum ContentType {
     Add,
     Remove,
+    #[default]
     Neutral,
 }
 
-impl Default for ContentType {
-    fn default() -> Self {
-        Self::Neutral
-    }
-}
-
-impl ToString for ContentType {
-    fn to_string(&self) -> String {
+impl Display for ContentType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ContentType::Add => "+".to_string(),
-            ContentType::Remove => "-".to_string(),
-            ContentType::Neutral => "".to_string(),
+            ContentType::Add => write!(f, "+"),
+            ContentType::Remove => write!(f, "-"),
+            ContentType::Neutral => Ok(()),
         }
     }
 }
 
+/// A single line of a diff hunk. `line_data` borrows from the diff output
+/// whenever the line is valid UTF-8, and excludes the `+`/`-`/` ` prefix.
 #[derive(Debug, PartialEq, Clone)]
-pub struct Content {
-    pub line_data: String,
+pub struct Content<'a> {
+    pub line_data: Cow<'a, str>,
     pub c_type: ContentType,
+    /// followed by `\ No newline at end of file`
+    pub no_newline: bool,
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Chunk {
+impl Content<'_> {
+    pub fn into_owned(self) -> Content<'static> {
+        Content {
+            line_data: Cow::Owned(self.line_data.into_owned()),
+            ..self
+        }
+    }
+}
+
+/// A hunk of a file diff, starting at the `@@ -removed_start,removed_changes
+/// +added_start,added_changes @@ heading` header.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Chunk<'a> {
     pub added_start: usize,
     pub added_changes: usize,
     pub removed_start: usize,
     pub removed_changes: usize,
-    pub content: Vec<Content>,
+    /// the text after the header, usually the enclosing function
+    pub heading: Cow<'a, str>,
+    pub content: Vec<Content<'a>>,
+}
+
+impl Chunk<'_> {
+    pub fn into_owned(self) -> Chunk<'static> {
+        Chunk {
+            heading: Cow::Owned(self.heading.into_owned()),
+            content: self.content.into_iter().map(Content::into_owned).collect(),
+            ..self
+        }
+    }
+
+    /// The line number of every line in the old and the new file. Added lines
+    /// have no old line number, and removed lines no new one.
+    pub fn line_numbers(&self) -> impl Iterator<Item = (Option<usize>, Option<usize>)> + '_ {
+        let (mut old, mut new) = (self.removed_start, self.added_start);
+        self.content.iter().map(move |c| match c.c_type {
+            ContentType::Add => {
+                new += 1;
+                (None, Some(new - 1))
+            }
+            ContentType::Remove => {
+                old += 1;
+                (Some(old - 1), None)
+            }
+            ContentType::Neutral => {
+                old += 1;
+                new += 1;
+                (Some(old - 1), Some(new - 1))
+            }
+        })
+    }
 }
 
 /* STATEMENTS */
 
-pub trait StatementTrait {
-    fn fmt(&self, options: Arc<Options>) -> (String, usize);
-    // TODO: add some print option here. OR we can try to implement Debug for implemented structs
+#[derive(Debug, PartialEq, Clone)]
+pub struct Statement<'a> {
+    pub a_file: Cow<'a, str>,
+    pub b_file: Cow<'a, str>,
+    /// the extended header lines between `diff --git` and the first chunk,
+    /// e.g. `new file mode 100644`, `--- a/file` and `+++ b/file`
+    pub header: Vec<Cow<'a, str>>,
+    pub chunks: Vec<Chunk<'a>>,
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Statement {
-    pub a_file: String,
-    pub b_file: String,
-    pub data: Vec<Content>,
+impl<'a> Statement<'a> {
+    pub fn into_owned(self) -> Statement<'static> {
+        Statement {
+            a_file: Cow::Owned(self.a_file.into_owned()),
+            b_file: Cow::Owned(self.b_file.into_owned()),
+            header: self
+                .header
+                .into_iter()
+                .map(|h| Cow::Owned(h.into_owned()))
+                .collect(),
+            chunks: self.chunks.into_iter().map(Chunk::into_owned).collect(),
+        }
+    }
+
+    /// Every line of every chunk.
+    pub fn lines(&self) -> impl Iterator<Item = &Content<'a>> {
+        self.chunks.iter().flat_map(|c| &c.content)
+    }
+
+    /// Whether the file is deleted, so that it only exists in the parent.
+    pub fn is_deletion(&self) -> bool {
+        self.header
+            .iter()
+            .any(|line| line.starts_with("deleted file mode") || line == "+++ /dev/null")
+    }
 }
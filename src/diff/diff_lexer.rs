@@ -1,7 +1,7 @@
 use std::fmt::Display;
-#[derive(Debug, PartialEq)]
-pub enum DiffToken {
-    Word(String),
+#[derive(Debug, PartialEq, Default)]
+pub enum DiffToken<'a> {
+    Word(&'a str),
     Int(usize),
 
     Diff,
@@ -16,11 +16,12 @@ pub enum DiffToken {
     TriplePlus,
     NewLine,
 
-    EOF,
+    Eof,
+    #[default]
     Illegal,
 }
 
-impl Display for DiffToken {
+impl Display for DiffToken<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DiffToken::Word(w) => write!(f, "{}", w),
@@ -34,45 +35,44 @@ impl Display for DiffToken {
             DiffToken::Plus => write!(f, "+"),
             DiffToken::TripleDash => write!(f, "---"),
             DiffToken::TriplePlus => write!(f, "+++"),
-            DiffToken::NewLine => write!(f, "\n"),
-            DiffToken::EOF => write!(f, "\0"),
+            DiffToken::NewLine => writeln!(f),
+            DiffToken::Eof => write!(f, "\0"),
             DiffToken::Illegal => write!(f, "ILLEGAL CHAR"),
         }
     }
 }
 
-impl Default for DiffToken {
-    fn default() -> Self {
-        DiffToken::Illegal
-    }
-}
-
-impl From<&str> for DiffToken {
-    fn from(s: &str) -> Self {
+impl<'a> From<&'a str> for DiffToken<'a> {
+    fn from(s: &'a str) -> Self {
         match s {
             "diff" => DiffToken::Diff,
             "index" => DiffToken::Index,
             "@@" => DiffToken::ChunkMarker,
-            _ => DiffToken::Word(s.into()),
+            _ => DiffToken::Word(s),
         }
     }
 }
 
-pub struct DiffLexer {
-    input: Vec<u8>,
+pub struct DiffLexer<'a> {
+    input: &'a [u8],
     pos: usize,
     ch: u8,
 }
 
-impl DiffLexer {
-    pub fn new(input: Vec<u8>) -> Self {
+impl<'a> DiffLexer<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
         Self {
-            ch: input[0],
+            ch: input.first().copied().unwrap_or(b'\0'),
             input,
             pos: 0,
         }
     }
 
+    fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+        self.ch = self.input.get(pos).copied().unwrap_or(b'\0');
+    }
+
     fn read_multiple(&mut self, n: usize) {
         for _ in 0..n {
             self.read_char();
@@ -80,13 +80,28 @@ impl DiffLexer {
     }
 
     fn read_char(&mut self) {
-        if self.pos + 1 >= self.input.len() {
-            self.pos += 1;
-            self.ch = b'\0';
-        } else {
-            self.pos += 1;
-            self.ch = self.input[self.pos];
-        }
+        self.seek(self.pos + 1);
+    }
+
+    /// Returns the raw bytes up to the next newline (exclusive) and moves the
+    /// lexer to the start of the following line. Hunk content is read this way
+    /// so lines are never split into tokens.
+    pub fn next_line(&mut self) -> Option<&'a [u8]> {
+        let (line, next) = self.line_at_pos()?;
+        self.seek(next);
+        Some(line)
+    }
+
+    pub fn peek_line(&self) -> Option<&'a [u8]> {
+        self.line_at_pos().map(|(line, _)| line)
+    }
+
+    fn line_at_pos(&self) -> Option<(&'a [u8], usize)> {
+        let rest = self.input.get(self.pos..).filter(|r| !r.is_empty())?;
+        Some(match memchr::memchr(b'\n', rest) {
+            Some(end) => (&rest[..end], self.pos + end + 1),
+            None => (rest, self.input.len()),
+        })
     }
 
     fn count_whitespace(&mut self) -> usize {
@@ -102,18 +117,18 @@ impl DiffLexer {
         count
     }
 
-    fn read_word(&mut self) -> DiffToken {
+    fn read_word(&mut self) -> DiffToken<'a> {
         let s_pos = self.pos;
         while !self.ch.is_ascii_whitespace() && self.ch != b'\0' && self.ch != b',' {
             self.read_char()
         }
-        let word =
-            std::str::from_utf8(&self.input[s_pos..self.pos]).expect("This should be valid ascii");
-        let a = word.into();
-        a
+        match std::str::from_utf8(&self.input[s_pos..self.pos]) {
+            Ok(word) => word.into(),
+            Err(_) => DiffToken::Illegal,
+        }
     }
 
-    fn read_int(&mut self) -> DiffToken {
+    fn read_int(&mut self) -> DiffToken<'a> {
         let s_pos = self.pos;
         while self.ch.is_ascii_digit() {
             self.read_char()
@@ -122,53 +137,39 @@ impl DiffLexer {
         let slice = std::str::from_utf8(slice).unwrap();
         let int = slice.parse::<usize>().unwrap();
 
-        return DiffToken::Int(int);
+        DiffToken::Int(int)
     }
 
     fn check_word(&self, word: &str) -> bool {
-        let mut offset = 0;
-        let bytes = word.as_bytes();
-        while self.ch != 0 && offset < word.len() {
-            if self.input[self.pos + offset] != bytes[offset] {
-                return false;
-            }
-            offset += 1;
-        }
-        return true;
+        self.input[self.pos.min(self.input.len())..].starts_with(word.as_bytes())
     }
 
-    pub fn match_dash(&mut self) -> DiffToken {
+    pub fn match_dash(&mut self) -> DiffToken<'a> {
         if self.check_word("--git") {
             self.read_multiple(5);
-            return DiffToken::Git;
+            DiffToken::Git
         } else if self.check_word("---") {
             self.read_multiple(3);
-            return DiffToken::TripleDash;
+            DiffToken::TripleDash
         } else {
             self.read_char();
-            return DiffToken::Dash;
+            DiffToken::Dash
         }
     }
 
     fn is_entire_int(&self) -> bool {
-        let mut pos = self.pos;
-        loop {
-            let ch = self.input[pos];
-            if ch.is_ascii_digit() {
-                pos += 1;
-            } else if ch.is_ascii_alphabetic() {
-                return false;
-            } else {
-                return true;
-            }
-        }
+        let rest = &self.input[self.pos..];
+        !rest
+            .iter()
+            .find(|ch| !ch.is_ascii_digit())
+            .is_some_and(|ch| ch.is_ascii_alphabetic())
     }
 
-    pub fn next_token(&mut self) -> (usize, DiffToken) {
+    pub fn next_token(&mut self) -> (usize, DiffToken<'a>) {
         let ws = self.count_whitespace();
 
         let t = match self.ch {
-            b'\0' => DiffToken::EOF,
+            b'\0' => DiffToken::Eof,
             b'\n' => DiffToken::NewLine,
             b',' => DiffToken::Comma,
             b'-' => return (ws, self.match_dash()),
@@ -198,17 +199,6 @@ impl DiffLexer {
 }
 #[cfg(test)]
 mod tests {
-    impl DiffLexer {
-        pub fn new_from_string(s: String) -> Self {
-            let input = s.into_bytes();
-            Self {
-                ch: input[0],
-                input,
-                pos: 0,
-            }
-        }
-    }
-
     use super::{DiffLexer, DiffToken};
 
     #[test]
@@ -223,31 +213,31 @@ mod tests {
         use DiffToken::*;
         let output = vec![
             (0, Dash),
-            (0, Word(String::from("enum"))),
-            (1, Word(String::from("AST"))),
-            (1, Word(String::from("{"))),
+            (0, Word("enum")),
+            (1, Word("AST")),
+            (1, Word("{")),
             (0, NewLine),
             (0, Plus),
-            (0, Word(String::from("use"))),
-            (1, Word(String::from("super::diff_ast::{Content"))),
+            (0, Word("use")),
+            (1, Word("super::diff_ast::{Content")),
             (0, Comma),
-            (1, Word(String::from("ContentType"))),
+            (1, Word("ContentType")),
             (0, Comma),
-            (1, Word(String::from("Statement};"))),
+            (1, Word("Statement};")),
             (0, NewLine),
             (0, Dash),
-            (2, Word(String::from("indentTwo"))),
+            (2, Word("indentTwo")),
             (0, NewLine),
             (0, Dash),
-            (4, Word(String::from("indentFour"))),
+            (4, Word("indentFour")),
             (0, NewLine),
             (0, Dash),
-            (4, Word(String::from("tabIndent"))),
+            (4, Word("tabIndent")),
             (0, NewLine),
-            (0, Word(String::from("}"))),
+            (0, Word("}")),
         ];
 
-        let mut l = DiffLexer::new_from_string(input.into());
+        let mut l = DiffLexer::new(input.as_bytes());
         println!("testing tokens");
         for out in output {
             let token = l.next_token();
@@ -270,22 +260,22 @@ diff
         let output = vec![
             DiffToken::Diff,
             DiffToken::Git,
-            DiffToken::Word(String::from("a/src/ast.rs")),
-            DiffToken::Word(String::from("b/src/ast.rs")),
+            DiffToken::Word("a/src/ast.rs"),
+            DiffToken::Word("b/src/ast.rs"),
             DiffToken::NewLine,
-            DiffToken::Word(String::from("deleted")),
-            DiffToken::Word(String::from("file")),
-            DiffToken::Word(String::from("mode")),
+            DiffToken::Word("deleted"),
+            DiffToken::Word("file"),
+            DiffToken::Word("mode"),
             DiffToken::Int(100644),
             DiffToken::NewLine,
             DiffToken::Index,
-            DiffToken::Word(String::from("318bd87..0000000")),
+            DiffToken::Word("318bd87..0000000"),
             DiffToken::NewLine,
             DiffToken::TripleDash,
-            DiffToken::Word(String::from("a/src/ast.rs")),
+            DiffToken::Word("a/src/ast.rs"),
             DiffToken::NewLine,
             DiffToken::TriplePlus,
-            DiffToken::Word(String::from("/dev/null")),
+            DiffToken::Word("/dev/null"),
             DiffToken::NewLine,
             DiffToken::ChunkMarker,
             DiffToken::Dash,
@@ -299,21 +289,36 @@ diff
             DiffToken::ChunkMarker,
             DiffToken::NewLine,
             DiffToken::Dash,
-            DiffToken::Word(String::from("enum")),
-            DiffToken::Word(String::from("Ast")),
-            DiffToken::Word(String::from("{")),
+            DiffToken::Word("enum"),
+            DiffToken::Word("Ast"),
+            DiffToken::Word("{"),
             DiffToken::NewLine,
             DiffToken::Dash,
-            DiffToken::Word(String::from("}")),
+            DiffToken::Word("}"),
             DiffToken::NewLine,
             DiffToken::Diff,
             DiffToken::NewLine,
         ];
 
-        let mut l = DiffLexer::new_from_string(input.into());
-        for i in 0..output.len() {
+        let mut l = DiffLexer::new(input.as_bytes());
+        for out in output {
             let (_, token) = l.next_token();
-            assert_eq!(output[i], token);
+            assert_eq!(out, token);
         }
     }
+
+    #[test]
+    fn reading_lines() {
+        let input = "@@ -1 +1 @@\n+a line, with words\n\n-last";
+        let mut l = DiffLexer::new(input.as_bytes());
+
+        assert_eq!(l.peek_line(), Some(&b"@@ -1 +1 @@"[..]));
+        assert_eq!(l.next_line(), Some(&b"@@ -1 +1 @@"[..]));
+        assert_eq!(l.next_line(), Some(&b"+a line, with words"[..]));
+        assert_eq!(l.next_line(), Some(&b""[..]));
+        assert_eq!(l.next_token(), (0, DiffToken::Dash));
+        assert_eq!(l.next_line(), Some(&b"last"[..]));
+        assert_eq!(l.next_line(), None);
+        assert_eq!(l.next_token(), (0, DiffToken::Eof));
+    }
 }
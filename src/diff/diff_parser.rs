@@ -1,40 +1,37 @@
+use std::borrow::Cow;
+
 use super::{
     diff_ast::{Chunk, Content, Program, Statement},
     diff_lexer::{DiffLexer, DiffToken},
 };
 use crate::diff::diff_ast::ContentType;
 
-pub struct DiffParser {
-    l: DiffLexer,
-    curr_token: DiffToken,
-    peek_token: DiffToken,
-    pre_whitespace: usize,
-    peek_whitespace: usize,
+pub(crate) const STATEMENT_START: &[u8] = b"diff --git ";
+const CHUNK_START: &[u8] = b"@@ ";
+
+pub struct DiffParser<'a> {
+    l: DiffLexer<'a>,
+    // tokenizer for the header line currently being parsed (`diff --git`, `@@`)
+    header: DiffLexer<'a>,
+    curr_token: DiffToken<'a>,
 }
 
-impl DiffParser {
-    pub fn new(mut l: DiffLexer) -> Self {
-        let (_, curr_token) = l.next_token();
-        let (_, peek_token) = l.next_token();
+impl<'a> DiffParser<'a> {
+    pub fn new(l: DiffLexer<'a>) -> Self {
         Self {
             l,
-            curr_token,
-            peek_token,
-            pre_whitespace: 0,
-            peek_whitespace: 0,
+            header: DiffLexer::new(&[]),
+            curr_token: DiffToken::Eof,
         }
     }
 
-    fn next_token(&mut self) {
-        self.curr_token = std::mem::take(&mut self.peek_token);
-        self.pre_whitespace = std::mem::take(&mut self.peek_whitespace);
-        (self.peek_whitespace, self.peek_token) = self.l.next_token();
+    fn start_header(&mut self, line: &'a [u8]) {
+        self.header = DiffLexer::new(line);
+        self.next_token();
     }
 
-    fn skip_until(&mut self, t: DiffToken) {
-        while self.curr_token != t && self.curr_token != DiffToken::EOF {
-            self.next_token();
-        }
+    fn next_token(&mut self) {
+        (_, self.curr_token) = self.header.next_token();
     }
 
     fn expect_token(&mut self, t: DiffToken) -> Result<(), String> {
@@ -54,7 +51,11 @@ impl DiffParser {
             return Err(format!("could not match word for token: {:?}", self.curr_token));
         };
         self.next_token();
-        self.expect_token(DiffToken::Comma)?;
+        // a range of a single line is written without the count, e.g. `@@ -1 +1 @@`
+        if self.curr_token != DiffToken::Comma {
+            return Ok((start, 1));
+        }
+        self.next_token();
         let DiffToken::Int(changes) = self.curr_token else {
             return Err(format!("could not match word for token: {:?}", self.curr_token));
         };
@@ -62,86 +63,61 @@ impl DiffParser {
         Ok((start, changes))
     }
 
-    fn parse_content_line(&mut self) -> Option<Content> {
-        let mut s = Vec::new();
-
-        let c_type = match self.curr_token {
-            DiffToken::Plus => {
-                self.next_token();
-                ContentType::Add
-            }
-            DiffToken::Dash => {
-                self.next_token();
-                ContentType::Remove
-            }
-            _ => ContentType::Neutral,
+    fn parse_content_line(line: &'a [u8]) -> Content<'a> {
+        let (c_type, data) = match line.first() {
+            Some(b'+') => (ContentType::Add, &line[1..]),
+            Some(b'-') => (ContentType::Remove, &line[1..]),
+            Some(b' ') => (ContentType::Neutral, &line[1..]),
+            _ => (ContentType::Neutral, line),
         };
 
-        while self.curr_token != DiffToken::NewLine
-            && self.curr_token != DiffToken::EOF
-            && !(self.curr_token == DiffToken::ChunkMarker && self.peek_token == DiffToken::Dash)
-            && !(self.curr_token == DiffToken::Diff && self.peek_token == DiffToken::Git)
-        {
-            let sub_str = self.curr_token.to_string();
-            s.push(format!("{}", " ".repeat(self.pre_whitespace)));
-            if !sub_str.is_empty() {
-                s.push(sub_str);
-            }
-            self.next_token()
-        }
-        if s.is_empty() {
-            return None;
+        Content {
+            line_data: String::from_utf8_lossy(data),
+            c_type,
+            no_newline: false,
         }
+    }
 
-        return Some(Content {
-            line_data: s.join(""),
-            c_type,
-        });
+    fn at_section_end(&self) -> bool {
+        match self.l.peek_line() {
+            Some(line) => line.starts_with(STATEMENT_START) || line.starts_with(CHUNK_START),
+            None => true,
+        }
     }
 
-    fn parse_content(&mut self) -> Vec<Content> {
-        let mut content_list = Vec::new();
-
-        use DiffToken::*;
-        loop {
-            let content = match self.curr_token {
-                Diff => {
-                    if self.peek_token == Git {
-                        break;
-                    }
-                    self.next_token();
-                    self.parse_content_line()
-                }
-                EOF => break,
-                ChunkMarker => break,
-                NewLine => {
-                    self.next_token();
-                    self.parse_content_line()
-                }
-                _ => self.parse_content_line(),
+    fn parse_content(&mut self) -> Vec<Content<'a>> {
+        let mut content_list: Vec<Content<'a>> = Vec::new();
+
+        while !self.at_section_end() {
+            let Some(line) = self.l.next_line() else {
+                break;
             };
-            if let Some(c) = content {
-                content_list.push(c);
+            // "\ No newline at end of file", about the line before it
+            if line.starts_with(b"\\") {
+                if let Some(last) = content_list.last_mut() {
+                    last.no_newline = true;
+                }
+                continue;
             }
+            content_list.push(Self::parse_content_line(line));
         }
 
         content_list
     }
 
-    fn skip_until_newline(&mut self) {
-        while self.curr_token != DiffToken::NewLine && self.curr_token != DiffToken::EOF {
-            self.next_token();
-        }
-    }
-
-    fn parse_chunk(&mut self) -> Result<Chunk, String> {
+    fn parse_chunk(&mut self, header: &'a [u8]) -> Result<Chunk<'a>, String> {
+        self.start_header(header);
         self.expect_token(DiffToken::ChunkMarker)?;
         self.expect_token(DiffToken::Dash)?;
         let (removed_start, removed_changes) = self.parse_diff_chunk_range()?;
         self.expect_token(DiffToken::Plus)?;
         let (added_start, added_changes) = self.parse_diff_chunk_range()?;
+        // the rest of the header line is the heading, after the closing `@@ `
+        let heading = memchr::memmem::find(&header[2..], b"@@")
+            .map(|end| &header[end + 4..])
+            .unwrap_or_default();
+        let heading = String::from_utf8_lossy(heading.strip_prefix(b" ").unwrap_or(heading));
         self.expect_token(DiffToken::ChunkMarker)?;
-        self.skip_until_newline(); // don't include the chunk context
 
         let content = self.parse_content();
 
@@ -150,47 +126,67 @@ impl DiffParser {
             added_changes,
             removed_start,
             removed_changes,
+            heading,
             content,
         })
     }
 
-    fn parse_statement(&mut self) -> Result<Statement, String> {
-        self.expect_token(DiffToken::Diff)?;
-        self.expect_token(DiffToken::Git)?;
-        let DiffToken::Word(ref a_file) = self.curr_token else {
+    fn parse_file_name(&mut self) -> Result<Cow<'a, str>, String> {
+        let DiffToken::Word(file) = self.curr_token else {
             return Err(format!("could not match word for token: {:?}", self.curr_token));
         };
-        let a_file = a_file.clone();
         self.next_token();
-        let DiffToken::Word(ref b_file) = self.curr_token else {
-            return Err(format!("could not match word for token: {:?}", self.curr_token));
-        };
-        let b_file = b_file.clone();
+        Ok(Cow::Borrowed(file))
+    }
+
+    fn parse_statement(&mut self, header: &'a [u8]) -> Result<Statement<'a>, String> {
+        self.start_header(header);
+        self.expect_token(DiffToken::Diff)?;
+        self.expect_token(DiffToken::Git)?;
+        let mut a_file = self.parse_file_name()?;
+        let mut b_file = self.parse_file_name()?;
+        // more words mean the names have spaces in them
+        let spaced = !matches!(self.curr_token, DiffToken::NewLine | DiffToken::Eof);
 
-        self.skip_until(DiffToken::ChunkMarker);
-        let mut lines = vec![];
-        while self.curr_token == DiffToken::ChunkMarker {
-            lines.append(&mut self.parse_chunk()?.content.clone());
+        // the extended header lines (index, mode, ---, +++) up to the first chunk
+        let mut extended = Vec::new();
+        while !self.at_section_end() {
+            let Some(line) = self.l.next_line() else {
+                break;
+            };
+            extended.push(String::from_utf8_lossy(line));
+        }
+        if spaced {
+            let names =
+                std::str::from_utf8(&header[STATEMENT_START.len()..]).map_err(|e| e.to_string())?;
+            (a_file, b_file) = spaced_file_names(names, &extended)
+                .ok_or_else(|| format!("could not find the file names in: {names}"))?;
+        }
+
+        let mut chunks = vec![];
+        while let Some(line) = self.l.peek_line().filter(|l| l.starts_with(CHUNK_START)) {
+            self.l.next_line();
+            chunks.push(self.parse_chunk(line)?);
         }
 
-        return Ok(Statement {
+        Ok(Statement {
             a_file,
             b_file,
-            data: lines,
-        });
+            header: extended,
+            chunks,
+        })
     }
 
-    pub fn parse_program(&mut self) -> Program {
+    pub fn parse_program(&mut self) -> Program<'a> {
         let mut program = Program::new();
 
-        while self.curr_token != DiffToken::EOF {
-            match self.parse_statement() {
+        while let Some(line) = self.l.next_line() {
+            if !line.starts_with(STATEMENT_START) {
+                continue;
+            }
+            match self.parse_statement(line) {
                 Ok(s) => program.statements.push(s),
-                Err(e) => {
-                    // println!("{:?}", program.statements);
-                    panic!("{e:?}");
-                    // program.errors.push(e);
-                }
+                Err(e) => program.errors.push(e),
             }
         }
 
@@ -198,9 +194,33 @@ impl DiffParser {
     }
 }
 
+// Names with spaces can't be split at a space, so like git, use the names of
+// a rename or copy, or split `a/<path> b/<path>` in the middle.
+fn spaced_file_names<'a>(
+    names: &'a str,
+    extended: &[Cow<str>],
+) -> Option<(Cow<'a, str>, Cow<'a, str>)> {
+    let header = |prefixes: [&str; 2]| {
+        extended
+            .iter()
+            .find_map(|line| prefixes.iter().find_map(|p| line.strip_prefix(p)))
+    };
+    let from = header(["rename from ", "copy from "]);
+    let to = header(["rename to ", "copy to "]);
+    if let (Some(from), Some(to)) = (from, to) {
+        return Some((format!("a/{from}").into(), format!("b/{to}").into()));
+    }
+
+    let names = names.trim_end();
+    let half = names.len() / 2;
+    let (a_file, b_file) = (names.get(..half)?, names.get(half + 1..)?);
+    (names.as_bytes()[half] == b' ' && a_file.get(2..) == b_file.get(2..))
+        .then_some((a_file.into(), b_file.into()))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::diff::diff_ast::{Content, ContentType, Statement};
+    use crate::diff::diff_ast::{Chunk, Content, ContentType, Statement};
 
     use super::{DiffLexer, DiffParser};
 
@@ -219,32 +239,50 @@ index 318bd87..0000000
 }"#;
         use ContentType::*;
         let match_statements = vec![Statement {
-            a_file: String::from("a/src/ast.rs"),
-            b_file: String::from("b/src/ast.rs"),
-            data: vec![
-                Content {
-                    line_data: String::from("use super::diff_ast::{Content, ContentType, Statement};"),
-                    c_type: Add,
-                },
-                Content {
-                    line_data: String::from("  indentTwo"),
-                    c_type: Remove,
-                },
-                Content {
-                    line_data: String::from("    indentFour"),
-                    c_type: Remove,
-                },
-                Content {
-                    line_data: String::from("    tabIndent"), // tab indent should also indent four
-                    c_type: Remove,
-                },
-                Content {
-                    line_data: String::from("}"),
-                    c_type: Neutral,
-                },
+            a_file: "a/src/ast.rs".into(),
+            b_file: "b/src/ast.rs".into(),
+            header: vec![
+                "deleted file mode 100644".into(),
+                "index 318bd87..0000000".into(),
+                "--- a/src/ast.rs".into(),
+                "+++ /dev/null".into(),
             ],
+            chunks: vec![Chunk {
+                removed_start: 1,
+                removed_changes: 8,
+                added_start: 0,
+                added_changes: 0,
+                heading: "".into(),
+                content: vec![
+                    Content {
+                        line_data: "use super::diff_ast::{Content, ContentType, Statement};".into(),
+                        c_type: Add,
+                        no_newline: false,
+                    },
+                    Content {
+                        line_data: "  indentTwo".into(),
+                        c_type: Remove,
+                        no_newline: false,
+                    },
+                    Content {
+                        line_data: "    indentFour".into(),
+                        c_type: Remove,
+                        no_newline: false,
+                    },
+                    Content {
+                        line_data: "\ttabIndent".into(), // lines are borrowed as-is, tabs included
+                        c_type: Remove,
+                        no_newline: false,
+                    },
+                    Content {
+                        line_data: "}".into(),
+                        c_type: Neutral,
+                        no_newline: false,
+                    },
+                ],
+            }],
         }];
-        let l = DiffLexer::new_from_string(input.into());
+        let l = DiffLexer::new(input.as_bytes());
         let mut t = DiffParser::new(l);
         let p = t.parse_program();
 
@@ -271,41 +309,150 @@ index 318bd87..0000000
 
         use ContentType::*;
         let match_statements = vec![Statement {
-            a_file: String::from("a/src/ast.rs"),
-            b_file: String::from("b/src/ast.rs"),
-            data: vec![
-                Content {
-                    line_data: String::from("enum Ast {"),
-                    c_type: Remove,
+            a_file: "a/src/ast.rs".into(),
+            b_file: "b/src/ast.rs".into(),
+            header: vec![
+                "deleted file mode 100644".into(),
+                "index 318bd87..0000000".into(),
+                "--- a/src/ast.rs".into(),
+                "+++ /dev/null".into(),
+            ],
+            chunks: vec![
+                Chunk {
+                    removed_start: 1,
+                    removed_changes: 8,
+                    added_start: 0,
+                    added_changes: 0,
+                    heading: "".into(),
+                    content: vec![
+                        Content {
+                            line_data: "enum Ast {".into(),
+                            c_type: Remove,
+                            no_newline: false,
+                        },
+                        // the leading context space is not part of the line
+                        Content {
+                            line_data: "    Testing // @@ a".into(),
+                            c_type: Neutral,
+                            no_newline: false,
+                        },
+                        Content {
+                            line_data: "}".into(),
+                            c_type: Remove,
+                            no_newline: false,
+                        },
+                    ],
                 },
-                Content {
-                    line_data: String::from("     Testing // @@ a"), // 5 spaces here since the
-                                                                     // content type is neutral
-                    c_type: Neutral,
-                },
-                Content {
-                    line_data: String::from("}"),
-                    c_type: Remove,
+                Chunk {
+                    removed_start: 10,
+                    removed_changes: 80,
+                    added_start: 10,
+                    added_changes: 60,
+                    heading: "".into(),
+                    content: vec![
+                        Content {
+                            line_data: "enum Test {".into(),
+                            c_type: Remove,
+                            no_newline: false,
+                        },
+                        Content {
+                            line_data: "    Hi".into(), // 4 spaces
+                            c_type: Add,
+                            no_newline: false,
+                        },
+                        Content {
+                            line_data: "}".into(),
+                            c_type: Remove,
+                            no_newline: false,
+                        },
+                    ],
                 },
+            ],
+        }];
+        let l = DiffLexer::new(input.as_bytes());
+        let mut t = DiffParser::new(l);
+        let p = t.parse_program();
+
+        assert_eq!(p.errors.len(), 0);
+        assert_eq!(p.statements, match_statements);
+    }
+
+    #[test]
+    fn content_lines_are_not_tokenized() {
+        let input = "diff --git a/a.txt b/a.txt
+index 318bd87..0f3e0a1 100644
+--- a/a.txt
++++ b/a.txt
+@@ -1 +1,2 @@
+-007, 12.5
++++counter;
++--flag
+\\ No newline at end of file
+diff --git a/b.txt b/b.txt
+";
+
+        use ContentType::*;
+        let l = DiffLexer::new(input.as_bytes());
+        let p = DiffParser::new(l).parse_program();
+
+        assert_eq!(p.errors.len(), 0);
+        assert_eq!(p.statements.len(), 2);
+        assert_eq!(
+            p.statements[0].chunks[0].content,
+            vec![
                 Content {
-                    line_data: String::from("enum Test {"),
+                    line_data: "007, 12.5".into(),
                     c_type: Remove,
+                    no_newline: false,
                 },
                 Content {
-                    line_data: String::from("    Hi"), // 4 spaces
+                    line_data: "++counter;".into(),
                     c_type: Add,
+                    no_newline: false,
                 },
                 Content {
-                    line_data: String::from("}"),
-                    c_type: Remove,
+                    line_data: "--flag".into(),
+                    c_type: Add,
+                    // followed by the "\ No newline at end of file" marker
+                    no_newline: true,
                 },
-            ],
-        }];
-        let l = DiffLexer::new_from_string(input.into());
-        let mut t = DiffParser::new(l);
-        let p = t.parse_program();
+            ]
+        );
+        assert!(p.statements[1].chunks.is_empty());
+    }
 
-        assert_eq!(p.errors.len(), 0);
-        assert_eq!(p.statements, match_statements);
+    #[test]
+    fn line_numbers_follow_chunk_headers() {
+        let input = "diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -3,3 +3,3 @@
+ three
+-four
++FOUR
+ five
+@@ -20 +20,2 @@
++nineteen
+ twenty
+";
+        let p = DiffParser::new(DiffLexer::new(input.as_bytes())).parse_program();
+        let numbers = p.statements[0]
+            .chunks
+            .iter()
+            .map(|c| c.line_numbers().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            numbers,
+            vec![
+                vec![
+                    (Some(3), Some(3)),
+                    (Some(4), None),
+                    (None, Some(4)),
+                    (Some(5), Some(5)),
+                ],
+                vec![(None, Some(20)), (Some(20), Some(21))],
+            ]
+        );
     }
 }
@@ -0,0 +1,114 @@
+use std::io::BufRead;
+
+use super::{
+    diff_ast::Statement,
+    diff_lexer::DiffLexer,
+    diff_parser::{DiffParser, STATEMENT_START},
+};
+
+/// Parses `git diff` output incrementally, yielding one `Statement` per file.
+/// Only the file diff currently being parsed is held in memory, but it is held
+/// whole: a statement's chunks are matched, cached and filtered together, so
+/// peak memory is bounded by the largest file diff rather than the largest
+/// chunk. Oversized diffs such as generated files are read whole before
+/// `HistorySearch::max_file_size` can skip them.
+pub struct DiffReader<R: BufRead> {
+    reader: R,
+    buf: Vec<u8>,
+    // `diff --git` line that ended the previous statement
+    next_header: Vec<u8>,
+}
+
+impl<R: BufRead> DiffReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            next_header: Vec::new(),
+        }
+    }
+
+    // reads lines into `buf` until the start of the next statement or EOF
+    fn fill_statement(&mut self) -> Result<(), String> {
+        self.buf.clear();
+        self.buf.append(&mut self.next_header);
+        loop {
+            let line_start = self.buf.len();
+            let read = self
+                .reader
+                .read_until(b'\n', &mut self.buf)
+                .map_err(|e| e.to_string())?;
+            if read == 0 {
+                return Ok(());
+            }
+            if line_start > 0 && self.buf[line_start..].starts_with(STATEMENT_START) {
+                self.next_header = self.buf.split_off(line_start);
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for DiffReader<R> {
+    type Item = Result<Statement<'static>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Err(e) = self.fill_statement() {
+                return Some(Err(e));
+            }
+            if self.buf.is_empty() {
+                return None;
+            }
+
+            let mut program = DiffParser::new(DiffLexer::new(&self.buf)).parse_program();
+            if let Some(e) = program.errors.pop() {
+                return Some(Err(e));
+            }
+            // anything before the first `diff --git` line doesn't produce a statement
+            if let Some(statement) = program.statements.pop() {
+                return Some(Ok(statement.into_owned()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiffReader;
+    use crate::diff::{diff_lexer::DiffLexer, diff_parser::DiffParser};
+
+    #[test]
+    fn reads_statements_one_at_a_time() {
+        let input = r#"warning: something before the diff
+diff --git a/src/ast.rs b/src/ast.rs
+index 318bd87..0f3e0a1 100644
+--- a/src/ast.rs
++++ b/src/ast.rs
+@@ -1,2 +1,2 @@
+-enum Ast {
++enum Tree {
+ }
+diff --git a/src/lib.rs b/src/lib.rs
+index 318bd87..0f3e0a1 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1 +1 @@
+- diff --git a/x b/x
++mod tree;
+"#;
+        let expected = DiffParser::new(DiffLexer::new(input.as_bytes())).parse_program();
+
+        let statements = DiffReader::new(input.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements, expected.statements);
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(DiffReader::new(&b""[..]).count(), 0);
+    }
+}
@@ -0,0 +1,200 @@
+use std::io::{self, Write};
+
+use super::diff_ast::{Chunk, ContentType, Statement};
+
+// `start,count`, or just `start` for a single line like git writes it
+fn range(start: usize, count: usize) -> String {
+    if count == 1 {
+        start.to_string()
+    } else {
+        format!("{start},{count}")
+    }
+}
+
+/// Writes a statement back out as a `diff --git` file diff.
+///
+/// Chunk headers are recomputed from the chunks' lines, so chunks can be left
+/// out and the diff still applies to the old file: every chunk's new start is
+/// shifted by the lines the chunks before it add and remove.
+pub fn write_statement(out: &mut impl Write, statement: &Statement) -> io::Result<()> {
+    writeln!(out, "diff --git {} {}", statement.a_file, statement.b_file)?;
+    for line in &statement.header {
+        writeln!(out, "{line}")?;
+    }
+
+    // lines added minus lines removed by the chunks written so far
+    let mut offset: isize = 0;
+    for chunk in &statement.chunks {
+        let (removed, added) = line_counts(chunk);
+        // an empty range starts at the line before it
+        let first_old = chunk.removed_start + usize::from(removed == 0);
+        let first_new = first_old.saturating_add_signed(offset);
+        let added_start = first_new - usize::from(added == 0);
+        offset += added as isize - removed as isize;
+
+        write!(
+            out,
+            "@@ -{} +{} @@",
+            range(chunk.removed_start, removed),
+            range(added_start, added)
+        )?;
+        if !chunk.heading.is_empty() {
+            write!(out, " {}", chunk.heading)?;
+        }
+        writeln!(out)?;
+
+        for content in &chunk.content {
+            let prefix = match content.c_type {
+                ContentType::Add => '+',
+                ContentType::Remove => '-',
+                ContentType::Neutral => ' ',
+            };
+            writeln!(out, "{prefix}{}", content.line_data)?;
+            if content.no_newline {
+                writeln!(out, "\\ No newline at end of file")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// the number of lines of the old and the new file in a chunk
+fn line_counts(chunk: &Chunk) -> (usize, usize) {
+    chunk
+        .content
+        .iter()
+        .fold((0, 0), |(removed, added), c| match c.c_type {
+            ContentType::Add => (removed, added + 1),
+            ContentType::Remove => (removed + 1, added),
+            ContentType::Neutral => (removed + 1, added + 1),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_statement;
+    use crate::diff::{diff_ast::Statement, diff_lexer::DiffLexer, diff_parser::DiffParser};
+
+    fn parse(input: &str) -> Vec<Statement<'_>> {
+        let program = DiffParser::new(DiffLexer::new(input.as_bytes())).parse_program();
+        assert_eq!(program.errors, Vec::<String>::new());
+        program.statements
+    }
+
+    fn write(statements: &[Statement]) -> String {
+        let mut out = Vec::new();
+        for statement in statements {
+            write_statement(&mut out, statement).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    const DIFF: &str = "diff --git a/src/main.rs b/src/main.rs
+index 318bd87..0f3e0a1 100644
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,4 +1,5 @@
+ use std::io;
++use std::fs;
+ 
+ fn main() {
+     let x = 1;
+@@ -10,3 +11,2 @@ fn main() {
+     let y = 2;
+-    let z = 3;
+ }
+@@ -20 +20,2 @@ fn helper() {
+-    old()
+\\ No newline at end of file
++    new();
++}
+\\ No newline at end of file
+diff --git a/new.txt b/new.txt
+new file mode 100644
+index 0000000..257cc56
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++foo
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+index 257cc56..0000000
+--- a/gone.txt
++++ /dev/null
+@@ -1,2 +0,0 @@
+-foo
+-bar
+diff --git a/old name.txt b/new name.txt
+similarity 100%
+rename from old name.txt
+rename to new name.txt
+diff --git a/with space.txt b/with space.txt
+index 257cc56..5716ca5 100644
+--- a/with space.txt
++++ b/with space.txt
+@@ -1 +1 @@
+-foo
++bar
+diff --git a/image.png b/image.png
+index 0000000..1111111 100644
+Binary files a/image.png and b/image.png differ
+diff --git a/run.sh b/run.sh
+old mode 100644
+new mode 100755
+";
+
+    #[test]
+    fn round_trip() {
+        let statements = parse(DIFF);
+
+        assert_eq!(write(&statements), DIFF);
+        assert_eq!(statements[4].b_file, "b/with space.txt");
+        assert_eq!(parse(&write(&statements)), statements);
+    }
+
+    #[test]
+    fn chunk_headers_are_recomputed() {
+        let mut statements = parse(DIFF);
+        let main = &mut statements[0];
+        // without the first chunk, the others are one line further up
+        main.chunks.remove(0);
+        // without its added line, the last chunk is one line shorter
+        main.chunks[1].content.pop();
+
+        let out = write(&statements[..1]);
+
+        let headers: Vec<&str> = out.lines().filter(|l| l.starts_with("@@")).collect();
+        assert_eq!(
+            headers,
+            [
+                "@@ -10,3 +10,2 @@ fn main() {",
+                "@@ -20 +19 @@ fn helper() {"
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_ranges() {
+        let statements = parse(
+            "diff --git a/a b/a
+--- a/a
++++ b/a
+@@ -3,0 +4,2 @@
++one
++two
+@@ -8,2 +9,0 @@
+-three
+-four
+",
+        );
+        let mut first_removed = statements.clone();
+        first_removed[0].chunks.remove(0);
+
+        assert_eq!(write(&statements).lines().nth(3), Some("@@ -3,0 +4,2 @@"));
+        assert_eq!(write(&statements).lines().nth(6), Some("@@ -8,2 +9,0 @@"));
+        assert_eq!(
+            write(&first_removed).lines().nth(3),
+            Some("@@ -8,2 +7,0 @@")
+        );
+    }
+}
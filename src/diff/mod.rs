@@ -1,4 +1,5 @@
 pub mod diff_parser;
 pub mod diff_lexer;
 pub mod diff_ast;
-pub mod diff_formatting;
+pub mod diff_reader;
+pub mod diff_writer;
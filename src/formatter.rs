@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use crate::ColorSettings;
 
 #[derive(Clone, Debug)]
@@ -8,15 +10,27 @@ pub enum Color {
     Cyan,
 }
 
-impl ToString for Color {
-    fn to_string(&self) -> String {
-        match self {
+impl Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
             Color::Green => "\x1b[32m",
             Color::Red => "\x1b[31m",
             Color::Yellow => "\x1b[33m",
             Color::Cyan => "\x1b[36m",
+        };
+        write!(f, "{code}")
+    }
+}
+
+impl Color {
+    /// The CSS color closest to the terminal color.
+    pub fn css(&self) -> &'static str {
+        match self {
+            Color::Green => "#2e7d32",
+            Color::Red => "#c62828",
+            Color::Yellow => "#9e7c00",
+            Color::Cyan => "#00838f",
         }
-        .to_string()
     }
 }
 
@@ -28,13 +42,13 @@ pub enum Styles {
     Color(Color),
 }
 
-impl ToString for Styles {
-    fn to_string(&self) -> String {
+impl Display for Styles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Styles::Bold => "\x1b[1m".to_string(),
-            Styles::Underline => "\x1b[4m".to_string(),
-            Styles::Italic => "\x1b[3m".to_string(),
-            Styles::Color(color) => color.to_string(),
+            Styles::Bold => write!(f, "\x1b[1m"),
+            Styles::Underline => write!(f, "\x1b[4m"),
+            Styles::Italic => write!(f, "\x1b[3m"),
+            Styles::Color(color) => write!(f, "{color}"),
         }
     }
 }
@@ -0,0 +1,24 @@
+//! Running git commands whose whole output is needed at once.
+
+use std::process::Command;
+
+use anyhow::{bail, Result};
+
+/// Runs `git args` in `target_dir` and returns its stdout, or its stderr as the
+/// error if it fails.
+pub(crate) fn git(target_dir: Option<&str>, args: &[&str]) -> Result<Vec<u8>> {
+    let mut com = Command::new("git");
+    com.args(args);
+    if let Some(t) = target_dir {
+        com.current_dir(t);
+    }
+    let out = com.output()?;
+    if !out.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(out.stdout)
+}
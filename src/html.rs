@@ -0,0 +1,221 @@
+//! A self-contained HTML report, with a collapsible section per commit.
+
+use std::io::{self, Write};
+
+use crate::{
+    diff::diff_ast::ContentType,
+    formatter::Color,
+    matcher::{CommitMatcher, DiffLine, FileMatches},
+    pattern::Span,
+    sink::{annotation_title, Sink, Summary},
+    Options,
+};
+
+const SCRIPT: &str = r#"
+const filter = document.getElementById("filter");
+filter.addEventListener("input", () => {
+  const query = filter.value.toLowerCase();
+  for (const commit of document.querySelectorAll("details.commit")) {
+    const found = commit.textContent.toLowerCase().includes(query);
+    commit.hidden = !found;
+    if (query) commit.open = found;
+  }
+});
+"#;
+
+fn style() -> String {
+    format!(
+        r#"
+body {{ font-family: sans-serif; margin: 2em; }}
+header {{ position: sticky; top: 0; background: white; padding-bottom: 1em; border-bottom: 1px solid #ccc; }}
+#filter {{ width: 30em; padding: 0.3em; }}
+details.commit {{ margin: 1em 0; border: 1px solid #ddd; border-radius: 4px; padding: 0.5em; }}
+details.commit > summary {{ cursor: pointer; }}
+.hash {{ font-family: monospace; color: {cyan}; font-weight: bold; }}
+.file h3 {{ font-size: 1em; font-style: italic; color: {cyan}; margin-bottom: 0.2em; }}
+pre {{ margin: 0.3em 0; }}
+.added {{ color: {green}; }}
+.removed {{ color: {red}; }}
+.break {{ color: #999; }}
+.annotation {{ margin: 0.3em 0; white-space: pre-wrap; }}
+mark {{ background: none; color: {cyan}; font-weight: bold; }}
+"#,
+        cyan = Color::Cyan.css(),
+        green = Color::Green.css(),
+        red = Color::Red.css(),
+    )
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// escaped `text` with the spans in `<mark>`
+fn mark(text: &str, spans: &[Span]) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+    for span in spans {
+        out.push_str(&escape(&text[pos..span.start]));
+        out.push_str(&format!(
+            "<mark>{}</mark>",
+            escape(&text[span.start..span.end])
+        ));
+        pos = span.end;
+    }
+    out.push_str(&escape(&text[pos..]));
+    out
+}
+
+/// Writes the results as a single HTML page, each commit as soon as it's
+/// found. The totals in the summary at the top are only known at the end, so a
+/// script at the end of the page fills them in.
+pub struct HtmlSink<W: Write> {
+    out: W,
+    skip_file_print: bool,
+    in_file: bool,
+}
+
+impl<W: Write> HtmlSink<W> {
+    pub fn new(out: W, options: &Options) -> Self {
+        Self {
+            out,
+            skip_file_print: options.skip_file_print,
+            in_file: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn close_file(&mut self) -> io::Result<()> {
+        if self.in_file {
+            self.out.write_all(b"</pre></section>\n")?;
+        }
+        self.in_file = false;
+        Ok(())
+    }
+
+    // `+    line` with the matched spans highlighted
+    fn line(&mut self, line: &DiffLine) -> io::Result<()> {
+        let class = match line.side() {
+            ContentType::Add => "added",
+            ContentType::Remove => "removed",
+            ContentType::Neutral => "context",
+        };
+        writeln!(
+            self.out,
+            "<span class=\"{class}\">{}    {}</span>",
+            line.side(),
+            mark(line.text(), line.spans())
+        )
+    }
+}
+
+impl<W: Write> Sink for HtmlSink<W> {
+    fn begin(&mut self, patterns: &[String]) -> io::Result<()> {
+        let patterns = patterns
+            .iter()
+            .map(|p| format!("<code>{}</code>", escape(p)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(
+            self.out,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>git_grep report</title>\n<style>{}</style>\n</head>\n<body>\n\
+             <header>\n<h1>git_grep report</h1>\n<p>Searched for: {patterns}</p>\n\
+             <p>Commits searched: <span id=\"commits\">&hellip;</span> &middot; \
+             Total matches: <span id=\"total_matches\">&hellip;</span></p>\n\
+             <input id=\"filter\" type=\"search\" placeholder=\"Filter commits\">\n</header>\n\
+             <main>\n",
+            style(),
+        )
+    }
+
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "<details class=\"commit\" open>\n<summary><span class=\"hash\">{}</span>{} \
+             {} <small>{} &middot; {} matches</small></summary>",
+            escape(&commit.diff_args().join(" ")),
+            match &commit.commit().label {
+                Some(label) => format!(" <b>{}</b>", escape(label)),
+                None => String::new(),
+            },
+            mark(&commit.commit().message, commit.message_spans()),
+            escape(&commit.commit().date),
+            commit.total_matches(),
+        )?;
+        for (annotation, spans) in commit
+            .commit()
+            .annotations
+            .iter()
+            .zip(commit.annotation_spans())
+        {
+            writeln!(
+                self.out,
+                "<div class=\"annotation\"><b>{}</b> {}</div>",
+                escape(&annotation_title(annotation)),
+                mark(&annotation.text, spans),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn file(&mut self, file: &FileMatches) -> io::Result<()> {
+        self.close_file()?;
+        self.in_file = true;
+        self.out.write_all(b"<section class=\"file\">\n")?;
+        if !self.skip_file_print {
+            writeln!(
+                self.out,
+                "<h3>{} {}</h3>\n<p>File matches: {}</p>",
+                escape(file.file_a()),
+                escape(file.file_b()),
+                file.matched_lines(),
+            )?;
+        }
+        self.out.write_all(b"<pre>")
+    }
+
+    fn block_break(&mut self) -> io::Result<()> {
+        writeln!(self.out, "<span class=\"break\">\u{22ef}</span>")
+    }
+
+    fn match_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        self.line(line)
+    }
+
+    fn context_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        self.line(line)
+    }
+
+    fn end_commit(&mut self, _commit: &CommitMatcher) -> io::Result<()> {
+        self.close_file()?;
+        self.out.write_all(b"</details>\n")?;
+        self.out.flush()
+    }
+
+    fn end(&mut self, summary: &Summary) -> io::Result<()> {
+        write!(
+            self.out,
+            "</main>\n<script>\n\
+             document.getElementById(\"commits\").textContent = \"{}\";\n\
+             document.getElementById(\"total_matches\").textContent = \"{}\";\n\
+             {SCRIPT}</script>\n</body>\n</html>\n",
+            summary.commits, summary.total_matches,
+        )?;
+        self.out.flush()
+    }
+}
@@ -0,0 +1,430 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{mpsc, Arc},
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    cache::{git_grep_dir, DiffKey},
+    diff::diff_ast::{ContentType, Statement},
+    matcher::{for_each_statement, parent_hash},
+    pretty_medium::reader::CommitReader,
+    Options,
+};
+
+const INDEX_HEADER: &[u8] = b"git_grep trigram index v1\n";
+
+type Trigram = [u8; 3];
+
+/// A trigram index over the added and removed lines of every indexed commit,
+/// stored in `<git dir>/git_grep/index`.
+///
+/// Each trigram maps to the `(commit, file)` pairs whose diff contains it, so
+/// a literal search only has to diff the commits that contain every trigram
+/// of the search string.
+#[derive(Debug, Default, PartialEq)]
+pub struct HistoryIndex {
+    // (commit, parent) pairs, in the order they were indexed
+    commits: Vec<(String, String)>,
+    commit_ids: HashMap<String, u32>,
+    files: Vec<String>,
+    file_ids: HashMap<String, u32>,
+    // sorted by (commit, file) and without duplicates
+    postings: HashMap<Trigram, Vec<(u32, u32)>>,
+}
+
+/// The commits a search has to look at, according to the index.
+#[derive(Debug)]
+pub struct Candidates {
+    indexed: HashSet<String>,
+    // matching commit -> files that may contain the search string
+    matching: HashMap<String, HashSet<String>>,
+}
+
+impl Candidates {
+    /// Commits missing from the index can't be ruled out.
+    pub fn may_match(&self, commit: &str) -> bool {
+        !self.indexed.contains(commit) || self.matching.contains_key(commit)
+    }
+
+    pub fn is_indexed(&self, commit: &str) -> bool {
+        self.indexed.contains(commit)
+    }
+
+    pub fn files(&self, commit: &str) -> Option<&HashSet<String>> {
+        self.matching.get(commit)
+    }
+}
+
+pub fn index_path(target_dir: Option<&str>) -> Result<PathBuf> {
+    Ok(git_grep_dir(target_dir)?.join("index"))
+}
+
+fn trigrams(line: &str) -> impl Iterator<Item = Trigram> + '_ {
+    line.as_bytes().windows(3).map(|w| [w[0], w[1], w[2]])
+}
+
+impl HistoryIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commits.is_empty()
+    }
+
+    pub fn contains(&self, commit: &str) -> bool {
+        self.commit_ids.contains_key(commit)
+    }
+
+    fn file_id(&mut self, file: &str) -> u32 {
+        if let Some(id) = self.file_ids.get(file) {
+            return *id;
+        }
+        let id = self.files.len() as u32;
+        self.files.push(file.to_string());
+        self.file_ids.insert(file.to_string(), id);
+        id
+    }
+
+    /// Adds the trigrams of a commit's file diffs, keyed by the file on the
+    /// commit's side of the diff.
+    pub fn add_commit(
+        &mut self,
+        commit: &str,
+        parent: &str,
+        files: Vec<(String, HashSet<Trigram>)>,
+    ) {
+        if self.contains(commit) {
+            return;
+        }
+        let commit_id = self.commits.len() as u32;
+        self.commits.push((commit.to_string(), parent.to_string()));
+        self.commit_ids.insert(commit.to_string(), commit_id);
+
+        for (file, trigrams) in files {
+            let file_id = self.file_id(&file);
+            for trigram in trigrams {
+                // commits are only ever appended, so the postings stay sorted
+                let postings = self.postings.entry(trigram).or_default();
+                if postings.last() != Some(&(commit_id, file_id)) {
+                    postings.push((commit_id, file_id));
+                }
+            }
+        }
+    }
+
+    /// Returns `None` when a pattern is too short to have trigrams, in which
+    /// case every commit has to be searched.
+    pub fn candidates(&self, patterns: &[String]) -> Option<Candidates> {
+        let mut matching = HashSet::new();
+        for pattern in patterns {
+            matching.extend(self.pattern_postings(pattern)?);
+        }
+
+        let mut candidates = Candidates {
+            indexed: self.commit_ids.keys().cloned().collect(),
+            matching: HashMap::new(),
+        };
+        for (commit_id, file_id) in matching {
+            let commit = &self.commits[commit_id as usize].0;
+            candidates
+                .matching
+                .entry(commit.clone())
+                .or_default()
+                .insert(self.files[file_id as usize].clone());
+        }
+        Some(candidates)
+    }
+
+    // the (commit, file) pairs that contain every trigram of the pattern
+    fn pattern_postings(&self, pattern: &str) -> Option<HashSet<(u32, u32)>> {
+        let mut needles: Vec<Trigram> = trigrams(pattern).collect();
+        needles.sort();
+        needles.dedup();
+
+        let mut matching: Option<HashSet<(u32, u32)>> = None;
+        for needle in needles {
+            let postings = self
+                .postings
+                .get(&needle)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            matching = Some(match matching {
+                None => postings.iter().copied().collect(),
+                Some(m) => postings.iter().copied().filter(|p| m.contains(p)).collect(),
+            });
+        }
+        matching
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path)?;
+        let mut r = data
+            .strip_prefix(INDEX_HEADER)
+            .ok_or_else(|| anyhow!("unsupported index format"))?;
+
+        let mut index = HistoryIndex::new();
+        for _ in 0..read_varint(&mut r)? {
+            let commit = read_string(&mut r)?;
+            let parent = read_string(&mut r)?;
+            index
+                .commit_ids
+                .insert(commit.clone(), index.commits.len() as u32);
+            index.commits.push((commit, parent));
+        }
+        for _ in 0..read_varint(&mut r)? {
+            let file = read_string(&mut r)?;
+            index
+                .file_ids
+                .insert(file.clone(), index.files.len() as u32);
+            index.files.push(file);
+        }
+        for _ in 0..read_varint(&mut r)? {
+            let mut trigram = [0; 3];
+            r.read_exact(&mut trigram)?;
+            let mut postings = Vec::new();
+            let mut commit_id = 0;
+            for _ in 0..read_varint(&mut r)? {
+                // commit ids are delta encoded
+                commit_id += read_varint(&mut r)? as u32;
+                postings.push((commit_id, read_varint(&mut r)? as u32));
+            }
+            index.postings.insert(trigram, postings);
+        }
+        Ok(index)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut out = INDEX_HEADER.to_vec();
+        write_varint(&mut out, self.commits.len() as u64);
+        for (commit, parent) in &self.commits {
+            write_string(&mut out, commit);
+            write_string(&mut out, parent);
+        }
+        write_varint(&mut out, self.files.len() as u64);
+        for file in &self.files {
+            write_string(&mut out, file);
+        }
+        write_varint(&mut out, self.postings.len() as u64);
+        for (trigram, postings) in &self.postings {
+            out.extend_from_slice(trigram);
+            write_varint(&mut out, postings.len() as u64);
+            let mut prev = 0;
+            for (commit_id, file_id) in postings {
+                write_varint(&mut out, (commit_id - prev) as u64);
+                write_varint(&mut out, *file_id as u64);
+                prev = *commit_id;
+            }
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::File::create(&tmp_path)?.write_all(&out)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// Collects the trigrams of every added and removed line, per file.
+pub fn statement_trigrams(statement: &Statement) -> (String, HashSet<Trigram>) {
+    let trigrams = statement
+        .lines()
+        .filter(|l| l.c_type != ContentType::Neutral)
+        .flat_map(|l| trigrams(&l.line_data))
+        .collect();
+    (statement.b_file.to_string(), trigrams)
+}
+
+type ChannelData = Result<(String, String, Vec<(String, HashSet<Trigram>)>)>;
+
+/// Indexes every commit reachable from HEAD that isn't in the index yet.
+/// Returns the number of commits that were added.
+pub fn update(index: &mut HistoryIndex, options: &Options) -> Result<usize> {
+    let mut log = Command::new("git");
+    log.args(["log", "--pretty=medium", "--parents"])
+        .stdout(Stdio::piped());
+    if let Some(t) = &options.target_dir {
+        log.current_dir(t);
+    }
+    let mut log = log.spawn()?;
+    let commits = CommitReader::new(BufReader::new(log.stdout.take().expect("stdout is piped")));
+
+    let (tx, rx) = mpsc::channel::<ChannelData>();
+    let options = Arc::new(options.clone());
+    // like `for_each_commit_match`, at most a few commits per core are diffed
+    // at a time
+    let max_pending = thread::available_parallelism().map_or(8, |n| n.get() * 4);
+    let mut pending = 0;
+    let mut added = 0;
+    for commit in commits {
+        let commit = commit?;
+        if index.contains(&commit.hash) {
+            continue;
+        }
+        while pending >= max_pending {
+            let (commit, parent, files) = rx.recv().expect("a commit is in flight")?;
+            index.add_commit(&commit, &parent, files);
+            added += 1;
+            pending -= 1;
+        }
+        pending += 1;
+        let tx = tx.clone();
+        let options = options.clone();
+        thread::spawn(move || {
+            let parent = parent_hash(&commit);
+            let mut files = Vec::new();
+            // only added and removed lines are indexed, so no context is needed
+            let key = DiffKey {
+                parent: &parent,
+                commit: &commit.hash,
+                context: 0,
+                pathspecs: &[],
+            };
+            let diffed = for_each_statement(&key, &options, |statement| {
+                files.push(statement_trigrams(statement))
+            });
+            _ = tx.send(diffed.map(|()| (commit.hash, parent, files)));
+        });
+    }
+    drop(tx);
+
+    for diffed in rx {
+        let (commit, parent, files) = diffed?;
+        index.add_commit(&commit, &parent, files);
+        added += 1;
+    }
+    log.wait()?;
+    Ok(added)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(r: &mut &[u8]) -> Result<u64> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        r.read_exact(&mut byte)?;
+        n |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(anyhow!("index is corrupt"))
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    write_varint(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn read_string(r: &mut &[u8]) -> Result<String> {
+    let len = read_varint(r)? as usize;
+    let mut buf = vec![0; len];
+    r.read_exact(&mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{statement_trigrams, HistoryIndex};
+    use crate::diff::{diff_lexer::DiffLexer, diff_parser::DiffParser};
+
+    fn index_diff(index: &mut HistoryIndex, commit: &str, parent: &str, diff: &str) {
+        let program = DiffParser::new(DiffLexer::new(diff.as_bytes())).parse_program();
+        let files = program.statements.iter().map(statement_trigrams).collect();
+        index.add_commit(commit, parent, files);
+    }
+
+    fn build_index() -> HistoryIndex {
+        let mut index = HistoryIndex::new();
+        index_diff(
+            &mut index,
+            "c2",
+            "c1",
+            r#"diff --git a/src/main.rs b/src/main.rs
+@@ -1,2 +1,2 @@
+-    println!("hello");
++    eprintln!("hello");
+ // context only
+diff --git a/README.md b/README.md
+@@ -1 +1 @@
+-old readme
++new readme
+"#,
+        );
+        index_diff(
+            &mut index,
+            "c1",
+            "c0",
+            r#"diff --git a/src/main.rs b/src/main.rs
+@@ -0,0 +1,3 @@
++fn main() {
++    println!("hello");
++}
+"#,
+        );
+        index
+    }
+
+    #[test]
+    fn candidates() {
+        let index = build_index();
+
+        let println = index.candidates(&["println!".to_string()]).unwrap();
+        assert!(println.may_match("c1"));
+        assert!(println.may_match("c2"));
+        assert_eq!(
+            println.files("c2").unwrap().iter().collect::<Vec<_>>(),
+            vec!["b/src/main.rs"]
+        );
+
+        let readme = index.candidates(&["readme".to_string()]).unwrap();
+        assert!(!readme.may_match("c1"));
+        assert!(readme.may_match("c2"));
+        // commits that were never indexed always have to be searched
+        assert!(readme.may_match("c3"));
+        assert!(!readme.is_indexed("c3"));
+
+        // context lines aren't indexed
+        assert!(!index
+            .candidates(&["context only".to_string()])
+            .unwrap()
+            .may_match("c2"));
+        assert!(index.candidates(&["ab".to_string()]).is_none());
+
+        let either = index
+            .candidates(&["readme".to_string(), "fn main".to_string()])
+            .unwrap();
+        assert!(either.may_match("c1"));
+        assert!(either.may_match("c2"));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("git_grep").join("index");
+        let index = build_index();
+
+        index.save(&path).unwrap();
+        assert_eq!(HistoryIndex::load(&path).unwrap(), index);
+    }
+}
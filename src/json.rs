@@ -0,0 +1,375 @@
+//! Machine readable output, as one JSON document or as JSON Lines events.
+//!
+//! Both formats share the same objects. `SCHEMA_VERSION` is bumped whenever a
+//! field is removed or changes meaning; new fields may be added at any time.
+//!
+//! A match object has the line numbers of the matched line in the parent
+//! (`old_line`) and in the commit (`new_line`), its `side` (`added` or
+//! `removed`), its `text`, the byte ranges of the matched `spans` in the text
+//! along with the index of the pattern that matched, and the lines around it in
+//! `context_before` and `context_after`. Context lines can have any side,
+//! including `context` for lines the commit didn't change.
+//!
+//! A commit's `label` is `null` for commits of the log, and names what else was
+//! diffed otherwise, e.g. `main...HEAD` for the net diff of a branch.
+//!
+//! A commit's `message_spans` are the byte ranges of matches in its `message`,
+//! which are only searched with `--in message`. Its `annotations` are the
+//! messages of the annotated tags pointing to it and its notes, each with a
+//! `kind` (`tag` or `note`), the tag's or the notes ref's `name`, the `text`
+//! and the `spans` of matches in it, which are only searched with `--in tags`
+//! and `--in notes`. The name of the default notes ref is empty.
+//!
+//! The document is `{"version", "patterns", "commits": [commit], "summary"}`,
+//! where every commit has its `files`, and every file its `matches`.
+//!
+//! JSON Lines output has one event per line, each with a `type`: a `begin`
+//! event with the `version` and `patterns`, then `commit`, `file` and `match`
+//! events in the order they're found, and a final `summary` event.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::{
+    diff::diff_ast::ContentType,
+    matcher::{CommitMatcher, DiffLine, FileMatches},
+    paths::strip_prefix,
+    pattern::Span,
+    pretty_medium::parser::AnnotationKind,
+    sink::{Sink, Summary},
+    Options,
+};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct CommitJson<'a> {
+    hash: &'a str,
+    label: Option<&'a str>,
+    parent: &'a str,
+    author: &'a str,
+    date: &'a str,
+    committer: &'a str,
+    committer_date: &'a str,
+    message: &'a str,
+    message_spans: &'a [Span],
+    annotations: Vec<AnnotationJson<'a>>,
+    total_matches: usize,
+}
+
+#[derive(Serialize)]
+struct AnnotationJson<'a> {
+    kind: &'static str,
+    name: &'a str,
+    text: &'a str,
+    spans: &'a [Span],
+}
+
+impl<'a> From<&'a CommitMatcher> for CommitJson<'a> {
+    fn from(commit: &'a CommitMatcher) -> Self {
+        Self {
+            hash: &commit.commit().hash,
+            label: commit.commit().label.as_deref(),
+            parent: commit.parent_hash(),
+            author: &commit.commit().author,
+            date: &commit.commit().date,
+            committer: &commit.commit().committer,
+            committer_date: &commit.commit().committer_date,
+            message: &commit.commit().message,
+            message_spans: commit.message_spans(),
+            annotations: commit
+                .commit()
+                .annotations
+                .iter()
+                .zip(commit.annotation_spans())
+                .map(|(annotation, spans)| AnnotationJson {
+                    kind: match annotation.kind {
+                        AnnotationKind::Tag => "tag",
+                        AnnotationKind::Note => "note",
+                    },
+                    name: &annotation.name,
+                    text: &annotation.text,
+                    spans,
+                })
+                .collect(),
+            total_matches: commit.total_matches(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct FileJson<'a> {
+    old_path: &'a str,
+    new_path: &'a str,
+    matched_lines: usize,
+}
+
+impl<'a> From<&'a FileMatches> for FileJson<'a> {
+    fn from(file: &'a FileMatches) -> Self {
+        Self {
+            old_path: strip_prefix(file.file_a(), "a/"),
+            new_path: strip_prefix(file.file_b(), "b/"),
+            matched_lines: file.matched_lines(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct LineJson<'a> {
+    old_line: Option<usize>,
+    new_line: Option<usize>,
+    side: &'static str,
+    text: &'a str,
+}
+
+impl<'a> From<&'a DiffLine> for LineJson<'a> {
+    fn from(line: &'a DiffLine) -> Self {
+        Self {
+            old_line: line.old_line(),
+            new_line: line.new_line(),
+            side: match line.side() {
+                ContentType::Add => "added",
+                ContentType::Remove => "removed",
+                ContentType::Neutral => "context",
+            },
+            text: line.text(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MatchJson<'a> {
+    #[serde(flatten)]
+    line: LineJson<'a>,
+    spans: &'a [Span],
+    context_before: Vec<LineJson<'a>>,
+    context_after: Vec<LineJson<'a>>,
+}
+
+// every match of a file, with up to `before` and `after` lines of context
+fn matches<'a>(file: &'a FileMatches, before: usize, after: usize) -> Vec<MatchJson<'a>> {
+    let mut matches = Vec::new();
+    for block in file.blocks() {
+        for (idx, line) in block.iter().enumerate().filter(|(_, l)| l.is_match()) {
+            let after_end = (idx + 1 + after).min(block.len());
+            matches.push(MatchJson {
+                line: line.into(),
+                spans: line.spans(),
+                context_before: block[idx.saturating_sub(before)..idx]
+                    .iter()
+                    .map(LineJson::from)
+                    .collect(),
+                context_after: block[idx + 1..after_end]
+                    .iter()
+                    .map(LineJson::from)
+                    .collect(),
+            });
+        }
+    }
+    matches
+}
+
+#[derive(Serialize)]
+struct SummaryJson {
+    commits: usize,
+    total_matches: usize,
+}
+
+impl From<&Summary> for SummaryJson {
+    fn from(summary: &Summary) -> Self {
+        Self {
+            commits: summary.commits,
+            total_matches: summary.total_matches,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DocumentFile<'a> {
+    #[serde(flatten)]
+    file: FileJson<'a>,
+    matches: Vec<MatchJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct DocumentCommit<'a> {
+    #[serde(flatten)]
+    commit: CommitJson<'a>,
+    files: Vec<DocumentFile<'a>>,
+}
+
+/// Writes the results as a single JSON document. Commits are written as soon
+/// as they're found, so the document is only complete once the search is.
+pub struct JsonSink<W: Write> {
+    out: W,
+    before_context: usize,
+    after_context: usize,
+    first_commit: bool,
+}
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(out: W, options: &Options) -> Self {
+        Self {
+            out,
+            before_context: options.before_context,
+            after_context: options.after_context,
+            first_commit: true,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Sink for JsonSink<W> {
+    fn begin(&mut self, patterns: &[String]) -> io::Result<()> {
+        write!(self.out, "{{\"version\":{SCHEMA_VERSION},\"patterns\":")?;
+        serde_json::to_writer(&mut self.out, patterns)?;
+        write!(self.out, ",\"commits\":[")
+    }
+
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()> {
+        if !self.first_commit {
+            write!(self.out, ",")?;
+        }
+        self.first_commit = false;
+
+        let files = commit
+            .file_matches()
+            .iter()
+            .map(|file| DocumentFile {
+                file: file.into(),
+                matches: matches(file, self.before_context, self.after_context),
+            })
+            .collect();
+        let commit = DocumentCommit {
+            commit: commit.into(),
+            files,
+        };
+        serde_json::to_writer(&mut self.out, &commit)?;
+        self.out.flush()
+    }
+
+    // the whole commit is written by `begin_commit`
+    fn file(&mut self, _file: &FileMatches) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn match_line(&mut self, _line: &DiffLine) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn context_line(&mut self, _line: &DiffLine) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end(&mut self, summary: &Summary) -> io::Result<()> {
+        write!(self.out, "],\"summary\":")?;
+        serde_json::to_writer(&mut self.out, &SummaryJson::from(summary))?;
+        writeln!(self.out, "}}")?;
+        self.out.flush()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event<'a> {
+    Begin {
+        version: u32,
+        patterns: &'a [String],
+    },
+    Commit(CommitJson<'a>),
+    File {
+        hash: &'a str,
+        #[serde(flatten)]
+        file: FileJson<'a>,
+    },
+    Match {
+        hash: &'a str,
+        path: &'a str,
+        #[serde(flatten)]
+        line: MatchJson<'a>,
+    },
+    Summary(SummaryJson),
+}
+
+/// Writes the results as JSON Lines, one event per line.
+pub struct JsonLinesSink<W: Write> {
+    out: W,
+    before_context: usize,
+    after_context: usize,
+    // the commit the following file events belong to
+    hash: String,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(out: W, options: &Options) -> Self {
+        Self {
+            out,
+            before_context: options.before_context,
+            after_context: options.after_context,
+            hash: String::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn event(&mut self, event: &Event) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, event)?;
+        writeln!(self.out)
+    }
+}
+
+impl<W: Write> Sink for JsonLinesSink<W> {
+    fn begin(&mut self, patterns: &[String]) -> io::Result<()> {
+        self.event(&Event::Begin {
+            version: SCHEMA_VERSION,
+            patterns,
+        })
+    }
+
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()> {
+        self.hash.clone_from(&commit.commit().hash);
+        self.event(&Event::Commit(commit.into()))
+    }
+
+    // the match events are written along with their file
+    fn file(&mut self, file: &FileMatches) -> io::Result<()> {
+        let hash = std::mem::take(&mut self.hash);
+        self.event(&Event::File {
+            hash: &hash,
+            file: file.into(),
+        })?;
+        let path = strip_prefix(file.file_b(), "b/");
+        for line in matches(file, self.before_context, self.after_context) {
+            self.event(&Event::Match {
+                hash: &hash,
+                path,
+                line,
+            })?;
+        }
+        self.hash = hash;
+        Ok(())
+    }
+
+    fn match_line(&mut self, _line: &DiffLine) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn context_line(&mut self, _line: &DiffLine) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end_commit(&mut self, _commit: &CommitMatcher) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn end(&mut self, summary: &Summary) -> io::Result<()> {
+        self.event(&Event::Summary(summary.into()))?;
+        self.out.flush()
+    }
+}
@@ -0,0 +1,90 @@
+//! Searches the added and removed lines in the history of a git repository.
+//!
+//! ```no_run
+//! use rust_aliaser::HistorySearch;
+//!
+//! let output = HistorySearch::new("unwrap()").max_count(100).run()?;
+//! println!("{} matches", output.total_matches());
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod attributes;
+pub mod cache;
+pub mod delimited;
+pub mod diff;
+pub mod formatter;
+mod git;
+pub mod html;
+pub mod index;
+pub mod json;
+pub mod matcher;
+pub mod patch;
+pub mod paths;
+pub mod pattern;
+pub mod pretty_medium;
+pub mod quickfix;
+pub mod sarif;
+pub mod search;
+pub mod sink;
+pub mod template;
+
+pub use matcher::{CommitMatcher, FileMatches, MatcherOutput};
+pub use pattern::Matcher;
+pub use search::HistorySearch;
+pub use sink::{Sink, Summary};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColorSettings {
+    Colored,
+    Uncolored,
+}
+
+/// What the patterns are matched against, only the diffs by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sources {
+    /// the added and removed lines of every commit
+    pub diff: bool,
+    /// the subject and body of every commit message
+    pub message: bool,
+    /// the messages of the annotated tags pointing to a commit
+    pub tags: bool,
+    /// the notes attached to a commit
+    pub notes: bool,
+}
+
+impl Default for Sources {
+    fn default() -> Self {
+        Self {
+            diff: true,
+            message: false,
+            tags: false,
+            notes: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub before_context: usize,
+    pub after_context: usize,
+    pub show_empty: bool,
+    /// what was searched for, `matcher` decides what matches
+    pub patterns: Vec<String>,
+    pub matcher: std::sync::Arc<dyn pattern::Matcher>,
+    pub sources: Sources,
+    /// only files matching these are diffed, every file if empty
+    pub pathspecs: Vec<String>,
+    /// only files it matches are searched, every file if `None`
+    pub path_filter: Option<std::sync::Arc<paths::PathFilter>>,
+    /// files marked generated, vendored or binary in these are skipped
+    pub attributes: Option<std::sync::Arc<attributes::Attributes>>,
+    /// files whose diff is larger than this many bytes are skipped
+    pub max_file_size: Option<u64>,
+    /// chunks with more lines than this are skipped
+    pub max_hunk_lines: Option<usize>,
+    pub skip_file_print: bool,
+    pub target_dir: Option<String>,
+    pub color: ColorSettings,
+    pub cache: Option<cache::DiffCache>,
+    pub candidates: Option<std::sync::Arc<index::Candidates>>,
+}
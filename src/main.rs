@@ -1,14 +1,23 @@
-mod diff;
-mod matcher;
-mod pretty_medium;
-mod formatter;
+use clap::{Parser as ClapParser, Subcommand, ValueEnum};
+use std::{
+    io::{self, BufWriter},
+    time::Instant,
+};
 
-use clap::{Parser as ClapParser, ValueEnum};
-use std::{process::Command, time::Instant};
-
-use pretty_medium::{lexer::Lexer, parser::Parser};
-
-use crate::matcher::{do_the_matching, MatchFormat};
+use rust_aliaser::{
+    cache::DiffCache,
+    delimited::{Column, DelimitedSink},
+    html::HtmlSink,
+    index::{self, HistoryIndex},
+    json::{JsonLinesSink, JsonSink},
+    patch::PatchSink,
+    paths,
+    quickfix::{self, QuickfixSink},
+    sarif::SarifSink,
+    sink::{SimpleSink, TerminalSink},
+    template::{Template, TemplateSink},
+    ColorSettings, HistorySearch, Sink, Sources,
+};
 
 #[derive(ValueEnum, Clone, Debug)]
 enum ColorInput {
@@ -17,17 +26,165 @@ enum ColorInput {
     Uncolored,
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum ColorSettings {
-    Colored,
-    Uncolored,
+#[derive(Clone, Debug)]
+enum Format {
+    Terminal,
+    Simple,
+    Json,
+    Jsonl,
+    Sarif,
+    Html,
+    Csv,
+    Tsv,
+    Vimgrep,
+    Patch,
+    Template(Template),
+}
+
+// a format name, or a template like `git log --pretty=format:`
+fn parse_format(format: &str) -> Result<Format, String> {
+    Ok(match format {
+        "terminal" => Format::Terminal,
+        "simple" => Format::Simple,
+        "json" => Format::Json,
+        "jsonl" => Format::Jsonl,
+        "sarif" => Format::Sarif,
+        "html" => Format::Html,
+        "csv" => Format::Csv,
+        "tsv" => Format::Tsv,
+        "vimgrep" | "quickfix" => Format::Vimgrep,
+        "patch" => Format::Patch,
+        _ => {
+            let template = match format.strip_prefix("format:") {
+                Some(template) => template,
+                None if format.contains('%') => format,
+                None => {
+                    return Err(format!(
+                        "unknown format {format}, expected terminal, simple, json, jsonl, \
+                         sarif, html, csv, tsv, vimgrep, patch or a template such as '%h %f:%n %l'"
+                    ))
+                }
+            };
+            Format::Template(Template::parse(template).map_err(|e| e.to_string())?)
+        }
+    })
+}
+
+// a number of bytes with an optional K, M or G suffix
+fn parse_size(size: &str) -> Result<u64, String> {
+    let (digits, unit) = match size.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&size[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&size[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&size[..i], 1 << 30),
+        _ => (size, 1),
+    };
+    let n: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size {size}, expected a number like 500K or 2M"))?;
+    Ok(n * unit)
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum SourceInput {
+    Diff,
+    Message,
+    /// the diff and the message
+    Both,
+    /// annotated tag messages
+    Tags,
+    /// git notes
+    Notes,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ColumnInput {
+    Commit,
+    Parent,
+    Author,
+    Date,
+    Notes,
+    File,
+    OldLine,
+    NewLine,
+    Side,
+    Pattern,
+    Text,
 }
 
 #[derive(ClapParser)]
-#[command(author, about, version)]
+#[command(author, about, version, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// search string
-    search: String,
+    #[arg(required_unless_present_any = ["pattern", "type_list"])]
+    search: Option<String>,
+
+    /// revisions and ranges to search, e.g. v1.2..v1.3 or main..feature, and revisions
+    /// to exclude like ^origin/main, HEAD by default. With -e the search string is the
+    /// first revision
+    revisions: Vec<String>,
+
+    /// search every branch, or the branches matching the glob
+    #[arg(long, value_name = "GLOB", num_args = 0..=1, require_equals = true)]
+    branches: Option<Option<String>>,
+
+    /// search every tag, or the tags matching the glob
+    #[arg(long, value_name = "GLOB", num_args = 0..=1, require_equals = true)]
+    tags: Option<Option<String>>,
+
+    /// search every remote-tracking branch, or those matching the glob
+    #[arg(long, value_name = "GLOB", num_args = 0..=1, require_equals = true)]
+    remotes: Option<Option<String>>,
+
+    /// search every ref and HEAD
+    #[arg(long)]
+    all: bool,
+
+    /// only search the commits HEAD adds to the merge base with BRANCH
+    #[arg(long, value_name = "BRANCH")]
+    since_merge_base: Option<String>,
+
+    /// also search the net diff since the merge base as a single commit
+    #[arg(long, requires = "since_merge_base")]
+    combined: bool,
+
+    /// also search the changes in the worktree that aren't staged
+    #[arg(long)]
+    worktree: bool,
+
+    /// also search the changes staged for the next commit
+    #[arg(long)]
+    staged: bool,
+
+    /// also search every stash entry
+    #[arg(long)]
+    stashes: bool,
+
+    /// also search the commits only reflogs still point to
+    #[arg(long)]
+    reflog: bool,
+
+    /// also search dangling commits that nothing reaches anymore
+    #[arg(long)]
+    unreachable: bool,
+
+    /// another search string, lines matching any of them are shown
+    #[arg(short = 'e', long)]
+    pattern: Vec<String>,
+
+    /// treat the search strings as regular expressions
+    #[arg(short = 'E', long)]
+    regex: bool,
+
+    /// what to search, e.g. --in message,diff or --in tags,notes
+    #[arg(long = "in", value_delimiter = ',', default_value = "diff")]
+    search_in: Vec<SourceInput>,
+
+    /// only search commits whose message matches the regular expression
+    #[arg(long)]
+    grep: Vec<String>,
 
     /// depth
     #[arg(short = 'D', long)]
@@ -37,6 +194,55 @@ struct Cli {
     #[arg(long)]
     show_empty: bool,
 
+    /// only search commits whose author matches the regular expression
+    #[arg(long)]
+    author: Vec<String>,
+
+    /// only search commits whose committer matches the regular expression
+    #[arg(long)]
+    committer: Vec<String>,
+
+    /// only search commits more recent than a date, e.g. 2024-01-01 or "2 weeks ago"
+    #[arg(long, visible_alias = "after")]
+    since: Option<String>,
+
+    /// only search commits older than a date
+    #[arg(long, visible_alias = "before")]
+    until: Option<String>,
+
+    /// only search files matching the glob, or not matching it if it starts with `!`
+    #[arg(short = 'g', long)]
+    glob: Vec<String>,
+
+    /// only search files of this type, e.g. rust or js
+    #[arg(short = 't', long = "type")]
+    file_type: Vec<String>,
+
+    /// don't search files of this type
+    #[arg(short = 'T', long = "type-not")]
+    file_type_not: Vec<String>,
+
+    /// print the file types and their globs
+    #[arg(long)]
+    type_list: bool,
+
+    /// git pathspecs of the files to search, after `--`
+    #[arg(last = true)]
+    pathspecs: Vec<String>,
+
+    /// also search files that .gitattributes marks as linguist-generated,
+    /// linguist-vendored, -diff or binary
+    #[arg(long)]
+    no_ignore_attributes: bool,
+
+    /// skip files whose diff is larger than SIZE, e.g. 500K or 2M
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_filesize: Option<u64>,
+
+    /// skip chunks with more than NUM lines
+    #[arg(long, value_name = "NUM")]
+    max_hunk_lines: Option<usize>,
+
     /// print NUM lines of leading context
     #[arg(short = 'B', long)]
     before_context: Option<usize>,
@@ -52,20 +258,79 @@ struct Cli {
     skip_file_print: bool,
 
     /// git directory to search in
-    #[arg(long)]
+    #[arg(long, global = true)]
     target_dir: Option<String>,
 
+    /// neither read nor write the diff cache in .git/git_grep
+    #[arg(long)]
+    no_cache: bool,
+
+    /// maximum size of the diff cache in MiB, least recently used diffs are removed first
+    #[arg(long, default_value_t = 512)]
+    cache_size: u64,
+
+    /// search every commit, even if `git_grep index` has ruled it out
+    #[arg(long)]
+    no_index: bool,
+
     #[arg(long, default_value = "auto")]
     color: Option<ColorInput>,
 
     /// print only the matched lines, with their commit and file diffs
     #[arg(long)]
     simple_print: bool,
+
+    /// print the results as a single JSON document
+    #[arg(long, conflicts_with_all = ["simple_print", "jsonl"])]
+    json: bool,
+
+    /// print the results as JSON Lines, one event per line
+    #[arg(long, conflicts_with = "simple_print")]
+    jsonl: bool,
+
+    /// print rev:path:line:col:text for every match, for editors' quickfix lists
+    #[arg(long, visible_alias = "quickfix", conflicts_with_all = ["simple_print", "json", "jsonl"])]
+    vimgrep: bool,
+
+    /// how to print the results: terminal, simple, json, jsonl, sarif, html, csv, tsv,
+    /// vimgrep, patch, or a template with placeholders like `git log --pretty=format:`, e.g. '%h %f:%n %l'
+    #[arg(long, value_parser = parse_format, conflicts_with_all = ["simple_print", "json", "jsonl", "vimgrep"])]
+    format: Option<Format>,
+
+    /// columns of the csv and tsv formats, all of them by default
+    #[arg(long, value_delimiter = ',')]
+    columns: Vec<ColumnInput>,
+
+    /// leave out the header row of the csv and tsv formats
+    #[arg(long)]
+    no_header: bool,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// manage the cache of parsed diffs
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// build or update the trigram index used to skip commits that can't match
+    Index,
+    /// write a file as it is in a revision to a temporary file and print its path
+    Materialize {
+        /// rev:path, or a line of --vimgrep output
+        spec: String,
+    },
 }
 
-impl Into<ColorSettings> for ColorInput {
-    fn into(self) -> ColorSettings {
-        match self {
+#[derive(Subcommand)]
+enum CacheAction {
+    /// remove every cached diff
+    Clear,
+}
+
+impl From<ColorInput> for ColorSettings {
+    fn from(color: ColorInput) -> Self {
+        match color {
             ColorInput::Auto => {
                 if atty::is(atty::Stream::Stdout) {
                     ColorSettings::Colored
@@ -79,62 +344,212 @@ impl Into<ColorSettings> for ColorInput {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Options {
-    before_context: usize,
-    after_context: usize,
-    show_empty: bool,
-    search_string: String,
-    skip_file_print: bool,
-    target_dir: Option<String>,
-    color: ColorSettings,
+impl From<ColumnInput> for Column {
+    fn from(column: ColumnInput) -> Self {
+        match column {
+            ColumnInput::Commit => Column::Commit,
+            ColumnInput::Parent => Column::Parent,
+            ColumnInput::Author => Column::Author,
+            ColumnInput::Date => Column::Date,
+            ColumnInput::Notes => Column::Notes,
+            ColumnInput::File => Column::File,
+            ColumnInput::OldLine => Column::OldLine,
+            ColumnInput::NewLine => Column::NewLine,
+            ColumnInput::Side => Column::Side,
+            ColumnInput::Pattern => Column::Pattern,
+            ColumnInput::Text => Column::Text,
+        }
+    }
 }
 
-impl From<Cli> for Options {
+impl From<Cli> for HistorySearch {
     fn from(cli: Cli) -> Self {
-        Self {
-            search_string: cli.search,
-            before_context: cli.before_context.unwrap_or(cli.context.unwrap_or(0)),
-            after_context: cli.after_context.unwrap_or(cli.context.unwrap_or(0)),
-            show_empty: cli.show_empty,
-            skip_file_print: cli.skip_file_print,
-            target_dir: cli.target_dir,
-            color: cli.color.unwrap_or(ColorInput::Auto).into(),
+        // like `grep -e`, the first positional isn't a pattern when -e is given
+        let (search, revisions) = if cli.pattern.is_empty() {
+            (cli.search, cli.revisions)
+        } else {
+            (None, cli.search.into_iter().chain(cli.revisions).collect())
+        };
+        let mut patterns = search.into_iter().chain(cli.pattern);
+        let mut search = HistorySearch::new(patterns.next().unwrap_or_default());
+        for pattern in patterns {
+            search = search.pattern(pattern);
+        }
+        let search_in = &cli.search_in;
+        let searches =
+            |source| search_in.contains(&source) || search_in.contains(&SourceInput::Both);
+        search = search
+            .regex(cli.regex)
+            .sources(Sources {
+                diff: searches(SourceInput::Diff),
+                message: searches(SourceInput::Message),
+                tags: search_in.contains(&SourceInput::Tags),
+                notes: search_in.contains(&SourceInput::Notes),
+            })
+            .before_context(cli.before_context.unwrap_or(cli.context.unwrap_or(0)))
+            .after_context(cli.after_context.unwrap_or(cli.context.unwrap_or(0)))
+            .show_empty(cli.show_empty)
+            .skip_file_print(cli.skip_file_print)
+            .color(cli.color.unwrap_or(ColorInput::Auto).into())
+            .cache(!cli.no_cache)
+            .cache_size(cli.cache_size * 1024 * 1024)
+            .index(!cli.no_index)
+            .attributes(!cli.no_ignore_attributes);
+        if let Some(bytes) = cli.max_filesize {
+            search = search.max_file_size(bytes);
+        }
+        if let Some(lines) = cli.max_hunk_lines {
+            search = search.max_hunk_lines(lines);
+        }
+        for revision in revisions {
+            search = search.revision(revision);
+        }
+        if let Some(glob) = &cli.branches {
+            search = search.branches(glob.as_deref());
+        }
+        if let Some(glob) = &cli.tags {
+            search = search.tags(glob.as_deref());
+        }
+        if let Some(glob) = &cli.remotes {
+            search = search.remotes(glob.as_deref());
         }
+        if cli.all {
+            search = search.all();
+        }
+        if let Some(branch) = cli.since_merge_base {
+            search = search.since_merge_base(branch).combined(cli.combined);
+        }
+        search = search
+            .worktree(cli.worktree)
+            .staged(cli.staged)
+            .stashes(cli.stashes)
+            .reflog(cli.reflog)
+            .unreachable(cli.unreachable);
+        for pattern in cli.grep {
+            search = search.grep(pattern);
+        }
+        for author in cli.author {
+            search = search.author(author);
+        }
+        for committer in cli.committer {
+            search = search.committer(committer);
+        }
+        if let Some(date) = cli.since {
+            search = search.since(date);
+        }
+        if let Some(date) = cli.until {
+            search = search.until(date);
+        }
+        for glob in cli.glob {
+            search = search.glob(glob);
+        }
+        for name in cli.file_type {
+            search = search.file_type(name);
+        }
+        for name in cli.file_type_not {
+            search = search.file_type_not(name);
+        }
+        for pathspec in cli.pathspecs {
+            search = search.path(pathspec);
+        }
+        if let Some(target_dir) = cli.target_dir {
+            search = search.repo(target_dir);
+        }
+        if let Some(depth) = cli.depth {
+            if depth == 0 {
+                panic!("depth must be greater than 0");
+            }
+            search = search.max_count(depth);
+        }
+        search
     }
 }
 
 fn main() {
     let now = Instant::now();
     let cli = Cli::parse();
-    let mut a = Command::new("git");
-    a.arg("log");
-    a.arg("--pretty=medium");
-    if let Some(target_dir) = &cli.target_dir {
-        a.current_dir(target_dir);
-    }
-    if let Some(depth) = cli.depth {
-        if depth == 0 {
-            panic!("depth must be greater than 0");
-        }
-        a.args(["-n", &(depth + 1).to_string()]); // +1 because the first line is the HEAD
-    }
-
-    let o = a.output().expect("failed command");
+    let cache_size = cli.cache_size * 1024 * 1024;
 
-    let l = Lexer::new(o.stdout);
-    let mut p = Parser::new(l);
-    let program = p.parse_program();
+    let target_dir = cli.target_dir.as_deref();
 
-    let simple_print = cli.simple_print;
+    match &cli.command {
+        Some(Commands::Cache { action }) => {
+            let cache =
+                DiffCache::open(target_dir, cache_size).expect("failed to find the git directory");
+            match action {
+                CacheAction::Clear => cache.clear().expect("failed to clear the cache"),
+            }
+            return;
+        }
+        Some(Commands::Index) => {
+            let index_path =
+                index::index_path(target_dir).expect("failed to find the git directory");
+            let mut index = HistoryIndex::load(&index_path).unwrap_or_default();
+            let mut options = HistorySearch::from(cli).options().clone();
+            options.cache = DiffCache::open(options.target_dir.as_deref(), cache_size).ok();
 
-    let options = Options::from(cli);
-    let matcher = do_the_matching(program, options.clone());
+            let added = index::update(&mut index, &options).expect("failed to index commits");
+            index.save(&index_path).expect("failed to save the index");
+            eprintln!("indexed {added} new commits, {} in total", index.len());
+            return;
+        }
+        Some(Commands::Materialize { spec }) => {
+            let path = quickfix::materialize(target_dir, spec)
+                .unwrap_or_else(|err| panic!("failed to materialize {spec}: {err}"));
+            println!("{}", path.display());
+            return;
+        }
+        None => {}
+    }
+    if cli.type_list {
+        for (name, globs) in paths::TYPES {
+            println!("{name}: {}", globs.join(", "));
+        }
+        return;
+    }
 
-    if simple_print {
-        println!("{}", matcher.simple_print(options));
+    let format = cli.format.clone().unwrap_or(if cli.json {
+        Format::Json
+    } else if cli.jsonl {
+        Format::Jsonl
+    } else if cli.vimgrep {
+        Format::Vimgrep
+    } else if cli.simple_print {
+        Format::Simple
+    } else {
+        Format::Terminal
+    });
+    let columns: Vec<Column> = if cli.columns.is_empty() {
+        Column::ALL.to_vec()
     } else {
-        println!("{}", matcher.print(options));
+        cli.columns.iter().map(|&c| c.into()).collect()
+    };
+    let header = !cli.no_header;
+    let search = HistorySearch::from(cli);
+
+    let stdout = BufWriter::new(io::stdout().lock());
+    let options = search.options();
+    let mut sink: Box<dyn Sink> = match format {
+        Format::Terminal => Box::new(TerminalSink::new(stdout, options)),
+        Format::Simple => Box::new(SimpleSink::new(stdout, options)),
+        Format::Json => Box::new(JsonSink::new(stdout, options)),
+        Format::Jsonl => Box::new(JsonLinesSink::new(stdout, options)),
+        Format::Sarif => Box::new(SarifSink::new(stdout)),
+        Format::Html => Box::new(HtmlSink::new(stdout, options)),
+        Format::Csv => Box::new(DelimitedSink::csv(stdout).columns(columns).header(header)),
+        Format::Tsv => Box::new(DelimitedSink::tsv(stdout).columns(columns).header(header)),
+        Format::Vimgrep => Box::new(QuickfixSink::new(stdout)),
+        Format::Patch => Box::new(PatchSink::new(stdout)),
+        Format::Template(template) => Box::new(TemplateSink::new(stdout, template, options)),
+    };
+    match search.stream(sink.as_mut()) {
+        Ok(_) => {}
+        // the reader went away, e.g. `git_grep ... | head`
+        Err(err)
+            if err
+                .downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) => {}
+        Err(err) => panic!("failed to search the history: {err}"),
     }
     eprintln!("time elapsed: {}", now.elapsed().as_millis());
 }
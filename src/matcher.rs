@@ -1,287 +1,695 @@
 use std::{
+    collections::BTreeMap,
+    io::BufReader,
+    process::{Command, Stdio},
     sync::{self, Arc},
     thread,
 };
 
+use anyhow::bail;
+
 use crate::{
-    diff::{diff_lexer::DiffLexer, diff_parser::DiffParser},
-    formatter::{Color, StyleBuilder, Styles},
-    pretty_medium::parser::{Commit, Program},
+    cache::{self, DiffKey},
+    diff::{
+        diff_ast::{ContentType, Statement},
+        diff_reader::DiffReader,
+    },
+    paths::statement_path,
+    pattern::{normalize_spans, Span},
+    pretty_medium::parser::{AnnotationKind, Commit, Uncommitted},
     Options,
 };
 
+// the tree every root commit is diffed against
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// The result of a search: every searched commit, in log order.
+#[derive(Debug)]
 pub struct MatcherOutput {
-    search_string: String,
+    patterns: Vec<String>,
     commit_matches: Vec<CommitMatcher>,
     total_matches: usize,
 }
 
+impl MatcherOutput {
+    pub fn new(patterns: Vec<String>, commit_matches: Vec<CommitMatcher>) -> Self {
+        let total_matches = commit_matches.iter().map(|c| c.total_matches).sum();
+        Self {
+            patterns,
+            commit_matches,
+            total_matches,
+        }
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Every searched commit, including those without matches.
+    pub fn commit_matches(&self) -> &[CommitMatcher] {
+        &self.commit_matches
+    }
+
+    pub fn total_matches(&self) -> usize {
+        self.total_matches
+    }
+
+    /// Every matched line, with the commit and file it was found in.
+    pub fn matches(&self) -> impl Iterator<Item = Match<'_>> {
+        self.commit_matches.iter().flat_map(|commit| {
+            commit.file_matches.iter().flat_map(move |file| {
+                file.lines()
+                    .filter(|line| line.is_match())
+                    .map(move |line| Match { commit, file, line })
+            })
+        })
+    }
+}
+
+/// A single matched line.
+#[derive(Debug, Clone, Copy)]
+pub struct Match<'a> {
+    pub commit: &'a CommitMatcher,
+    pub file: &'a FileMatches,
+    pub line: &'a DiffLine,
+}
+
+/// The matches in the diff between a commit and its first parent.
+#[derive(Debug)]
 pub struct CommitMatcher {
     commit: Commit,
-    previous_hash: String,
+    parent_hash: String,
+    message_spans: Vec<Span>,
+    message_matches: usize,
+    annotation_spans: Vec<Vec<Span>>,
     file_matches: Vec<FileMatches>,
     total_matches: usize,
 }
 
+impl CommitMatcher {
+    pub fn commit(&self) -> &Commit {
+        &self.commit
+    }
+
+    /// What the outputs call the commit: its hash, or the label of uncommitted
+    /// changes, which aren't a commit yet.
+    pub fn name(&self) -> &str {
+        match (self.commit.uncommitted, &self.commit.label) {
+            (Some(_), Some(label)) => label,
+            _ => &self.commit.hash,
+        }
+    }
+
+    /// The first parent, or the empty tree for root commits.
+    pub fn parent_hash(&self) -> &str {
+        &self.parent_hash
+    }
+
+    /// The revision that has the lines of `side`: the commit for added lines
+    /// and its parent for removed ones. For uncommitted changes, `""` is the
+    /// index and `None` the worktree, so `{rev}:{path}` names the file as git
+    /// does.
+    pub fn revision(&self, side: &ContentType) -> Option<&str> {
+        let removed = *side == ContentType::Remove;
+        match (self.commit.uncommitted, removed) {
+            (None | Some(Uncommitted::Staged), true) => Some(&self.parent_hash),
+            (None, false) => Some(&self.commit.hash),
+            (Some(Uncommitted::Staged), false) | (Some(Uncommitted::Unstaged), true) => Some(""),
+            (Some(Uncommitted::Unstaged), false) => None,
+        }
+    }
+
+    /// The arguments of `git diff` that show what was searched: the parent
+    /// and the commit, or e.g. `--cached` for staged changes.
+    pub fn diff_args(&self) -> Vec<&str> {
+        match self.commit.uncommitted {
+            Some(uncommitted) => uncommitted.diff_args().to_vec(),
+            None => vec![&self.parent_hash, &self.commit.hash],
+        }
+    }
+
+    /// Byte ranges of the matched text in the commit message, sorted and not
+    /// overlapping. Empty unless messages are searched.
+    pub fn message_spans(&self) -> &[Span] {
+        &self.message_spans
+    }
+
+    /// The number of matched lines in the commit message.
+    pub fn message_matches(&self) -> usize {
+        self.message_matches
+    }
+
+    /// The matches in each of the commit's annotations, like
+    /// `message_spans`. Empty for the tags or notes that aren't searched.
+    pub fn annotation_spans(&self) -> &[Vec<Span>] {
+        &self.annotation_spans
+    }
+
+    /// Only files with at least one match are included.
+    pub fn file_matches(&self) -> &[FileMatches] {
+        &self.file_matches
+    }
+
+    /// Matched lines in the diff, the message and the annotations.
+    pub fn total_matches(&self) -> usize {
+        self.total_matches
+    }
+}
+
 #[derive(Debug)]
-struct FileMatches {
-    file_a: String,
-    file_b: String,
-    content: String,
+pub struct FileMatches {
+    hunks: Statement<'static>,
+    blocks: Vec<Vec<DiffLine>>,
     matched_lines: usize,
 }
 
-type ChannelData = (CommitMatcher, usize);
+impl FileMatches {
+    /// The file's path in the parent, prefixed with `a/`.
+    pub fn file_a(&self) -> &str {
+        &self.hunks.a_file
+    }
 
-pub fn do_the_matching(program: Program, options: Options) -> MatcherOutput {
-    let (tx, rx) = sync::mpsc::channel::<ChannelData>();
-    let messages: usize = program.0.len() - 1;
-    let options_arc = Arc::new(options.clone());
+    /// The file's path in the commit, prefixed with `b/`.
+    pub fn file_b(&self) -> &str {
+        &self.hunks.b_file
+    }
 
-    for c_idx in 1..program.0.len() {
-        let tx = tx.clone();
-        let options_arc = options_arc.clone();
+    /// The file diff with only the chunks that have a match, e.g. to write it
+    /// as a patch with `diff_writer::write_statement`.
+    pub fn hunks(&self) -> &Statement<'static> {
+        &self.hunks
+    }
 
-        let commit = program.0[c_idx].clone();
-        let previous_hash = program.0[c_idx - 1].hash.clone();
+    /// Runs of consecutive diff lines: the matched lines and their context.
+    pub fn blocks(&self) -> &[Vec<DiffLine>] {
+        &self.blocks
+    }
 
-        thread::spawn(move || {
-            let commit_match = CommitMatcher::find_matches(commit, previous_hash, options_arc);
-            let num_matches = commit_match.total_matches;
-            _ = tx.send((commit_match, num_matches));
-        });
+    /// The lines of every block.
+    pub fn lines(&self) -> impl Iterator<Item = &DiffLine> {
+        self.blocks.iter().flatten()
     }
-    let mut commit_matches: Vec<CommitMatcher> = Vec::new();
-    let mut total_matches: usize = 0;
-    let mut message_num: usize = 0;
 
-    for (commit, num_matches) in rx {
-        commit_matches.push(commit);
+    pub fn matched_lines(&self) -> usize {
+        self.matched_lines
+    }
 
-        message_num += 1;
-        total_matches += num_matches;
+    fn new(statement: &Statement, options: &Options) -> Self {
+        let mut blocks = Vec::new();
+        let mut chunks = Vec::new();
+        let mut matched_lines = 0;
 
-        if message_num >= messages {
-            break;
+        // context never extends past the chunk a match is in
+        for chunk in &statement.chunks {
+            let matched: Vec<bool> = chunk
+                .content
+                .iter()
+                .map(|l| l.c_type != ContentType::Neutral && options.matcher.is_match(&l.line_data))
+                .collect();
+            if matched.contains(&true) {
+                chunks.push(chunk.clone().into_owned());
+            }
+            let mut shown = vec![false; matched.len()];
+            for idx in (0..matched.len()).filter(|&idx| matched[idx]) {
+                let last = (idx + options.after_context).min(shown.len() - 1);
+                shown[idx.saturating_sub(options.before_context)..=last].fill(true);
+            }
+
+            let mut block = Vec::new();
+            let numbered = chunk.content.iter().zip(chunk.line_numbers());
+            for (idx, (line, (old_line, new_line))) in numbered.enumerate() {
+                if !shown[idx] {
+                    if !block.is_empty() {
+                        blocks.push(std::mem::take(&mut block));
+                    }
+                    continue;
+                }
+                let spans = if matched[idx] {
+                    matched_lines += 1;
+                    normalize_spans(&line.line_data, options.matcher.find_spans(&line.line_data))
+                } else {
+                    Vec::new()
+                };
+                block.push(DiffLine {
+                    text: line.line_data.to_string(),
+                    side: line.c_type.clone(),
+                    old_line,
+                    new_line,
+                    spans,
+                    matched: matched[idx],
+                });
+            }
+            if !block.is_empty() {
+                blocks.push(block);
+            }
+        }
+
+        Self {
+            hunks: Statement {
+                a_file: statement.a_file.to_string().into(),
+                b_file: statement.b_file.to_string().into(),
+                header: statement
+                    .header
+                    .iter()
+                    .map(|h| h.to_string().into())
+                    .collect(),
+                chunks,
+            },
+            blocks,
+            matched_lines,
         }
     }
+}
 
-    return MatcherOutput {
-        commit_matches,
-        total_matches,
-        search_string: options.search_string,
-    };
+/// A line of a file diff included in the results, either a match or context
+/// around one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffLine {
+    text: String,
+    side: ContentType,
+    old_line: Option<usize>,
+    new_line: Option<usize>,
+    spans: Vec<Span>,
+    matched: bool,
+}
+
+impl DiffLine {
+    /// The line without its `+`/`-`/` ` prefix.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether the line was added, removed or is unchanged context.
+    pub fn side(&self) -> &ContentType {
+        &self.side
+    }
+
+    /// The line number in the parent, `None` for added lines.
+    pub fn old_line(&self) -> Option<usize> {
+        self.old_line
+    }
+
+    /// The line number in the commit, `None` for removed lines.
+    pub fn new_line(&self) -> Option<usize> {
+        self.new_line
+    }
+
+    /// Byte ranges of the matched text, sorted and not overlapping. Empty for
+    /// context lines.
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    pub fn is_match(&self) -> bool {
+        self.matched
+    }
 }
 
-#[allow(dead_code)]
-enum CommitMatcherErrors {
-    DiffError(Vec<String>),
-    EmptyDiff,
+// a commit's position in the log, and its matches or why they couldn't be found
+type ChannelData = (usize, anyhow::Result<CommitMatcher>);
+
+// hands out commit matches in log order, holding back those that finish early
+struct Reorder<F> {
+    pending: BTreeMap<usize, CommitMatcher>,
+    next: usize,
+    f: F,
 }
 
-impl CommitMatcher {
-    fn find_matches(commit: Commit, previous_commit: String, options: Arc<Options>) -> Self {
-        let mut diff_args = vec!["diff", &commit.hash, &previous_commit];
-        // get additional context from git diff if needed
-        let context_needed = options.before_context.max(options.after_context);
-        let with_context = &format!("-U{}", context_needed);
-        if context_needed > 0 {
-            diff_args.push(with_context);
+impl<F: FnMut(CommitMatcher) -> anyhow::Result<()>> Reorder<F> {
+    fn insert(&mut self, seq: usize, commit_match: CommitMatcher) -> anyhow::Result<()> {
+        self.pending.insert(seq, commit_match);
+        while let Some(commit_match) = self.pending.remove(&self.next) {
+            (self.f)(commit_match)?;
+            self.next += 1;
         }
+        Ok(())
+    }
+}
+
+/// Matches every commit and calls `f` with the results in the order of
+/// `commits`. Commits are diffed on their own threads, and at most a few per
+/// core are in flight or waiting for an earlier commit at any time. Stops at
+/// the first error returned by `f` or the first diff git fails to produce.
+pub fn for_each_commit_match<I>(
+    commits: I,
+    options: &Options,
+    mut f: impl FnMut(CommitMatcher) -> anyhow::Result<()>,
+) -> anyhow::Result<()>
+where
+    I: IntoIterator<Item = anyhow::Result<Commit>>,
+{
+    let (tx, rx) = sync::mpsc::channel::<ChannelData>();
+    let options_arc = Arc::new(options.clone());
+    let max_pending = thread::available_parallelism().map_or(8, |n| n.get() * 4);
 
-        let mut com = std::process::Command::new("git");
-        com.args(diff_args);
-        if let Some(t) = &options.target_dir {
-            com.current_dir(t);
+    let mut reorder = Reorder {
+        pending: BTreeMap::new(),
+        next: 0,
+        f: &mut f,
+    };
+    let mut unindexed = 0;
+    let mut total = 0;
+
+    // commits are diffed as soon as `git log` produces them
+    for commit in commits {
+        let commit = match commit {
+            Ok(commit) => commit,
+            Err(err) => {
+                eprintln!("failed to parse commit: {err}");
+                continue;
+            }
+        };
+        let seq = total;
+        total += 1;
+
+        // the index only knows a commit's diff against its first parent
+        let candidates = options
+            .candidates
+            .as_ref()
+            .filter(|_| commit.label.is_none());
+        if let Some(candidates) = candidates {
+            if !candidates.is_indexed(&commit.hash) {
+                unindexed += 1;
+            } else if !candidates.may_match(&commit.hash) {
+                // the index rules this commit out, so there's no need to diff it
+                reorder.insert(seq, CommitMatcher::new(commit, options))?;
+                continue;
+            }
         }
 
-        let diff = com.output().expect(&format!(
-            "failed diff for commits {}, {previous_commit}",
-            commit.hash
-        ));
-        let str_diff = std::str::from_utf8(&diff.stdout).expect("couldn't read file");
-
-        // early exit if there is no content from the diff
-        if str_diff.len() == 0 {
-            return CommitMatcher {
-                commit,
-                previous_hash: previous_commit.to_string(),
-                file_matches: Vec::new(),
-                total_matches: 0,
-            };
+        while seq - reorder.next >= max_pending {
+            let (done, commit_match) = rx.recv().expect("a commit is in flight");
+            reorder.insert(done, commit_match?)?;
         }
 
-        let diff_l = DiffLexer::new(str_diff.as_bytes().to_vec());
-        let mut diff_p = DiffParser::new(diff_l);
-        let diff_program = diff_p.parse_program();
+        let tx = tx.clone();
+        let options_arc = options_arc.clone();
+        thread::spawn(move || {
+            _ = tx.send((seq, CommitMatcher::find_matches(commit, options_arc)));
+        });
+    }
+    drop(tx);
 
-        let mut matches: Vec<FileMatches> = Vec::new();
-        let mut total_matches: usize = 0;
+    for (done, commit_match) in rx {
+        reorder.insert(done, commit_match?)?;
+    }
+    if options.candidates.is_some() && unindexed > 0 {
+        eprintln!("note: {unindexed} commits are not indexed yet, run `git_grep index` to update the index");
+    }
+    Ok(())
+}
 
-        for statement in diff_program.statements.into_iter() {
-            let (content, matched_lines) = statement.fmt(options.clone());
+pub fn do_the_matching<I>(commits: I, options: Options) -> anyhow::Result<MatcherOutput>
+where
+    I: IntoIterator<Item = anyhow::Result<Commit>>,
+{
+    let mut commit_matches = Vec::new();
+    for_each_commit_match(commits, &options, |commit_match| {
+        commit_matches.push(commit_match);
+        Ok(())
+    })?;
+    Ok(MatcherOutput::new(options.patterns, commit_matches))
+}
 
-            if matched_lines > 0 {
-                matches.push(FileMatches {
-                    file_a: statement.a_file,
-                    file_b: statement.b_file,
-                    content,
-                    matched_lines,
-                });
-                total_matches += matched_lines;
+/// Calls `f` with every file diff between `parent` and `commit`, read from the
+/// diff cache when possible. Fresh diffs are added to the cache as they are parsed.
+/// Fails if git can't diff the commits.
+pub fn for_each_statement(
+    key: &DiffKey,
+    options: &Options,
+    mut f: impl FnMut(&Statement),
+) -> anyhow::Result<()> {
+    let DiffKey {
+        parent,
+        commit,
+        context,
+        pathspecs,
+    } = *key;
+    let cache = options.cache.as_ref();
+    if let Some(entry) = cache.and_then(|c| c.read(key)) {
+        for statement in cache::statements(&entry) {
+            match statement {
+                Ok(statement) => f(&statement),
+                Err(err) => eprintln!("{err} for commit {commit}"),
             }
         }
-        CommitMatcher {
-            commit,
-            previous_hash: previous_commit.to_string(),
-            file_matches: matches,
-            total_matches,
+        return Ok(());
+    }
+
+    let mut writer = cache.and_then(|c| {
+        c.writer(key)
+            .map_err(|err| eprintln!("failed to write cache entry: {err}"))
+            .ok()
+    });
+    let diffed = diff(
+        &[parent, commit],
+        context,
+        pathspecs,
+        options,
+        |statement| {
+            match statement {
+                Ok(statement) => {
+                    if let Some(w) = &mut writer {
+                        if w.write_statement(&statement).is_err() {
+                            writer = None;
+                        }
+                    }
+                    f(&statement);
+                }
+                Err(err) => {
+                    eprintln!("failed to parse diff for commit {commit}: {err}");
+                    // don't cache a diff we couldn't fully parse
+                    writer = None;
+                }
+            }
+        },
+    );
+    if let Some(w) = writer.filter(|_| diffed.is_ok()) {
+        if let Err(err) = w.finish() {
+            eprintln!("failed to write cache entry: {err}");
         }
     }
+    diffed
 }
 
-pub trait MatchFormat {
-    fn print(&self, options: Options) -> String;
-    fn simple_print(&self, options: Options) -> String;
-}
+// runs `git diff` with `args` and calls `f` with every file diff as it's
+// parsed, fails if git does
+fn diff(
+    args: &[&str],
+    context: usize,
+    pathspecs: &[String],
+    options: &Options,
+    mut f: impl FnMut(Result<Statement<'static>, String>),
+) -> anyhow::Result<()> {
+    let mut diff_args = vec!["diff"];
+    diff_args.extend(args);
+    let with_context = &format!("-U{}", context);
+    if context > 0 {
+        diff_args.push(with_context);
+    }
+    if !pathspecs.is_empty() {
+        diff_args.push("--");
+        diff_args.extend(pathspecs.iter().map(String::as_str));
+    }
 
-impl MatchFormat for MatcherOutput {
-    fn print(&self, options: Options) -> String {
-        let cyan_bold = StyleBuilder::new(&options.color)
-            .add_style(Styles::Color(Color::Cyan))
-            .add_style(Styles::Bold);
-        let mut out = String::new();
-        out.push_str(&format!(
-            "{} \"{}\"\n{} {}\n\n",
-            cyan_bold.build("Searched For:"),
-            self.search_string,
-            cyan_bold.build("Total Matches:"),
-            self.total_matches
-        ));
-
-        out.push_str(
-            &self
-                .commit_matches
-                .iter()
-                .filter(|commit_match| options.show_empty || commit_match.total_matches > 0)
-                .map(|commit_match| commit_match.print(options.clone()))
-                .collect::<Vec<String>>()
-                .join("~~~\n\n"),
-        );
-        out.trim().to_string()
+    let mut com = Command::new("git");
+    com.args(&diff_args).stdout(Stdio::piped());
+    if let Some(t) = &options.target_dir {
+        com.current_dir(t);
     }
 
-    fn simple_print(&self, options: Options) -> String {
-        self.commit_matches
-            .iter()
-            .filter(|commit_match| options.show_empty || commit_match.total_matches > 0)
-            .map(|commit_match| commit_match.simple_print(options.clone()))
-            .collect::<Vec<String>>()
-            .join("\n")
+    let mut child = com.spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    for statement in DiffReader::new(BufReader::new(stdout)) {
+        f(statement);
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("git {} failed with {status}", diff_args.join(" "));
     }
+    Ok(())
 }
 
-impl MatchFormat for CommitMatcher {
-    fn print(&self, options: Options) -> String {
-        let cyan = StyleBuilder::new(&options.color);
-        let cyan_bold = cyan.clone().add_style(Styles::Bold);
-
-        let mut out = String::new();
-        out.push_str(&format!(
-            "{} {} {}\n",
-            cyan.build("git diff"),
-            cyan_bold.build(&self.commit.hash),
-            cyan_bold.build(&self.previous_hash),
-        ));
-        out.push_str(&format!(
-            "{} {}\n",
-            cyan.build("message:"),
-            cyan_bold.build(&self.commit.message),
-        ));
-        out.push_str(&format!(
-            "{} {}\n",
-            cyan.build("date:"),
-            cyan_bold.build(&self.commit.date),
-        ));
-        out.push_str(&format!(
-            "{} {}\n",
-            cyan.build("Commit matches:"),
-            cyan_bold.build(&self.total_matches.to_string()),
-        ));
-        out.push_str("\n");
-        out.push_str(
-            &self
-                .file_matches
-                .iter()
-                .filter(|file_match| options.show_empty || file_match.matched_lines > 0)
-                .map(|file_match| file_match.print(options.clone()))
-                .collect::<Vec<String>>()
-                .join("\n"),
-        );
-        out
-    }
-
-    fn simple_print(&self, options: Options) -> String {
-        let cyan = StyleBuilder::new(&options.color);
-        let cyan_bold = cyan.clone().add_style(Styles::Bold);
-
-        let mut out = String::new();
-        out.push_str(&format!(
-            "{} {} {}\n",
-            cyan.build("git diff"),
-            cyan_bold.build(&self.commit.hash),
-            cyan_bold.build(&self.previous_hash),
-        ));
-        out.push_str(
-            &self
-                .file_matches
-                .iter()
-                .filter(|file_match| options.show_empty || file_match.matched_lines > 0)
-                .map(|file_match| file_match.simple_print(options.clone()))
-                .collect::<Vec<String>>()
-                .join("\n"),
-        );
-
-        out.push_str("\n");
-        out
+// the number of matched lines in a commit message or annotation, and the
+// matches as byte ranges of the whole text
+fn text_spans(text: &str, options: &Options) -> (usize, Vec<Span>) {
+    let mut matched_lines = 0;
+    let mut spans = Vec::new();
+    let mut offset = 0;
+    for line in text.split('\n') {
+        let line_spans = normalize_spans(line, options.matcher.find_spans(line));
+        if !line_spans.is_empty() {
+            matched_lines += 1;
+        }
+        spans.extend(line_spans.into_iter().map(|s| Span {
+            start: s.start + offset,
+            end: s.end + offset,
+            ..s
+        }));
+        offset += line.len() + 1;
     }
+    (matched_lines, spans)
 }
 
-impl MatchFormat for FileMatches {
-    fn print(&self, options: Options) -> String {
-        let cyan = StyleBuilder::new(&options.color);
-        let cyan_it = cyan.clone().add_style(Styles::Italic);
-        let cyan_bold = cyan.clone().add_style(Styles::Bold);
-
-        let mut out = String::new();
-        if !options.skip_file_print {
-            // print file details
-            out.push_str(&format!(
-                "{}\n",
-                cyan_it.build(&format!("file diff: {} {}", &self.file_a, &self.file_b)),
-            ));
-            out.push_str(&format!(
-                "{} {}\n",
-                cyan.build("File matches:"),
-                cyan_bold.build(&self.matched_lines.to_string()),
-            ));
+/// The hash a commit is diffed against: its first parent, or the empty tree
+/// for root commits.
+pub fn parent_hash(commit: &Commit) -> String {
+    commit
+        .parents
+        .first()
+        .cloned()
+        .unwrap_or_else(|| EMPTY_TREE.to_string())
+}
+
+impl CommitMatcher {
+    // a commit with the matches in its message and annotations, before its
+    // diff is searched
+    fn new(commit: Commit, options: &Options) -> Self {
+        let (message_matches, message_spans) = if options.sources.message {
+            text_spans(&commit.message, options)
+        } else {
+            (0, Vec::new())
+        };
+        let mut total_matches = message_matches;
+        let annotation_spans = commit
+            .annotations
+            .iter()
+            .map(|annotation| {
+                let searched = match annotation.kind {
+                    AnnotationKind::Tag => options.sources.tags,
+                    AnnotationKind::Note => options.sources.notes,
+                };
+                if !searched {
+                    return Vec::new();
+                }
+                let (matches, spans) = text_spans(&annotation.text, options);
+                total_matches += matches;
+                spans
+            })
+            .collect();
+        CommitMatcher {
+            parent_hash: parent_hash(&commit),
+            commit,
+            message_spans,
+            message_matches,
+            annotation_spans,
+            file_matches: Vec::new(),
+            total_matches,
         }
-        out.push_str(&self.content);
-        out
     }
 
-    fn simple_print(&self, options: Options) -> String {
-        let cyan_it = StyleBuilder::new(&options.color).add_style(Styles::Italic);
+    fn find_matches(commit: Commit, options: Arc<Options>) -> anyhow::Result<Self> {
+        let mut commit_matcher = CommitMatcher::new(commit, &options);
+        if !options.sources.diff {
+            return Ok(commit_matcher);
+        }
+        // get additional context from git diff if needed
+        let context_needed = options.before_context.max(options.after_context);
+        let parent_hash = commit_matcher.parent_hash.clone();
+        let hash = commit_matcher.commit.hash.clone();
+        let uncommitted = commit_matcher.commit.uncommitted;
+
+        // the index knows which files of a candidate commit can contain the search string
+        let files = options
+            .candidates
+            .as_ref()
+            .filter(|_| commit_matcher.commit.label.is_none())
+            .and_then(|c| c.files(&hash));
+        let path_filter = options.path_filter.as_deref();
+        let mut add_statement = |statement: &Statement| {
+            if files.is_some_and(|f| !f.contains(statement.b_file.as_ref())) {
+                return;
+            }
+            if path_filter.is_some_and(|f| !f.is_statement_match(statement)) {
+                return;
+            }
+            commit_matcher.add_statement(statement, &options)
+        };
+        if let Some(uncommitted) = uncommitted {
+            // the worktree and the index change, so their diffs aren't cached
+            let args = uncommitted.diff_args();
+            diff(
+                args,
+                context_needed,
+                &options.pathspecs,
+                &options,
+                |statement| match statement {
+                    Ok(statement) => add_statement(&statement),
+                    Err(err) => eprintln!("failed to parse git diff {}: {err}", args.join(" ")),
+                },
+            )?;
+        } else {
+            let key = DiffKey {
+                parent: &parent_hash,
+                commit: &hash,
+                context: context_needed,
+                pathspecs: &options.pathspecs,
+            };
+            for_each_statement(&key, &options, add_statement)?;
+        }
 
-        let mut out = String::new();
-        if !options.skip_file_print {
-            // print file details
-            out.push_str(&format!(
-                "{}\n",
-                cyan_it.build(&format!("file diff: {} {}", &self.file_a, &self.file_b)),
-            ));
+        Ok(commit_matcher)
+    }
+
+    fn add_statement(&mut self, statement: &Statement, options: &Options) {
+        let path = statement_path(statement);
+        let hash = &self.commit.hash;
+        if let Some(max) = options.max_file_size {
+            let size: u64 = statement
+                .lines()
+                .map(|l| l.line_data.len() as u64 + 1)
+                .sum();
+            if size > max {
+                eprintln!("note: skipped {path} in {hash}, its diff of {size} bytes is too large");
+                return;
+            }
+        }
+        let trimmed;
+        let statement = match options.max_hunk_lines {
+            Some(max) if statement.chunks.iter().any(|c| c.content.len() > max) => {
+                for chunk in statement.chunks.iter().filter(|c| c.content.len() > max) {
+                    let (lines, line) = (chunk.content.len(), chunk.added_start);
+                    eprintln!("note: skipped {lines} lines at {path}:{line} in {hash}, the chunk is too long");
+                }
+                trimmed = Statement {
+                    a_file: statement.a_file.clone(),
+                    b_file: statement.b_file.clone(),
+                    header: statement.header.clone(),
+                    chunks: statement
+                        .chunks
+                        .iter()
+                        .filter(|c| c.content.len() <= max)
+                        .cloned()
+                        .collect(),
+                };
+                &trimmed
+            }
+            _ => statement,
+        };
+
+        let file_matches = FileMatches::new(statement, options);
+        if file_matches.matched_lines > 0 && !self.is_excluded(statement, options) {
+            self.total_matches += file_matches.matched_lines;
+            self.file_matches.push(file_matches);
         }
-        out.push_str(&self.content);
-        out
+    }
+
+    // only files with matches are looked up, most files have none
+    fn is_excluded(&self, statement: &Statement, options: &Options) -> bool {
+        let Some(attributes) = &options.attributes else {
+            return false;
+        };
+        // deleted files only exist in the parent
+        let rev = if statement.is_deletion() {
+            &self.parent_hash
+        } else {
+            &self.commit.hash
+        };
+        attributes
+            .is_excluded(rev, statement_path(statement))
+            .unwrap_or_else(|err| {
+                eprintln!("failed to read .gitattributes of {rev}: {err}");
+                false
+            })
     }
 }
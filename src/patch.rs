@@ -0,0 +1,82 @@
+//! The chunks that matched, as a patch.
+//!
+//! Every commit starts with a `git log` style header, followed by the chunks of
+//! its files that have a match. Chunk headers are recomputed for the chunks
+//! that are left out, so each commit's diffs apply to its parent with
+//! `git apply`, which skips the commit headers.
+
+use std::io::{self, Write};
+
+use crate::{
+    diff::diff_writer::write_statement,
+    matcher::{CommitMatcher, DiffLine, FileMatches},
+    pretty_medium::parser::AnnotationKind,
+    sink::{Sink, Summary},
+};
+
+/// Writes the matching chunks of every commit as a unified diff.
+pub struct PatchSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> PatchSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Sink for PatchSink<W> {
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()> {
+        writeln!(self.out, "commit {}", commit.name())?;
+        let commit = commit.commit();
+        writeln!(self.out, "Author: {}", commit.author)?;
+        writeln!(self.out, "Date:   {}\n", commit.date)?;
+        for line in commit.message.lines() {
+            writeln!(self.out, "    {}", line.trim())?;
+        }
+        writeln!(self.out)?;
+        // as `git log` shows notes, tags aren't part of a commit
+        for note in commit
+            .annotations
+            .iter()
+            .filter(|a| a.kind == AnnotationKind::Note)
+        {
+            match note.name.as_str() {
+                "" => writeln!(self.out, "Notes:")?,
+                name => writeln!(self.out, "Notes ({name}):")?,
+            }
+            for line in note.text.lines() {
+                writeln!(self.out, "    {}", line.trim())?;
+            }
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+
+    fn file(&mut self, file: &FileMatches) -> io::Result<()> {
+        if file.hunks().chunks.is_empty() {
+            return Ok(());
+        }
+        write_statement(&mut self.out, file.hunks())
+    }
+
+    fn match_line(&mut self, _line: &DiffLine) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn context_line(&mut self, _line: &DiffLine) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end_commit(&mut self, _commit: &CommitMatcher) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn end(&mut self, _summary: &Summary) -> io::Result<()> {
+        self.out.flush()
+    }
+}
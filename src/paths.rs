@@ -0,0 +1,219 @@
+//! Which files of a commit are searched: include and exclude globs, and
+//! file types like ripgrep's `-t rust` / `-T js`.
+//!
+//! A glob without a `/` matches the file name in any directory, other globs
+//! match the whole path from the repository root. A file is searched when it
+//! matches one of the include globs (if any), is of one of the selected types
+//! (if any), and matches neither an exclude glob nor a negated type.
+
+use anyhow::{anyhow, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::diff::diff_ast::Statement;
+
+/// The file types for `-t` and `-T`, with the globs of each.
+pub const TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    (
+        "cpp",
+        &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx", "*.h"],
+    ),
+    ("css", &["*.css", "*.scss", "*.sass", "*.less"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.jsx", "*.mjs", "*.cjs"]),
+    ("json", &["*.json"]),
+    ("markdown", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("ruby", &["*.rb", "Gemfile", "Rakefile"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.ts", "*.tsx", "*.mts", "*.cts"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+fn type_globs(name: &str) -> Result<&'static [&'static str]> {
+    TYPES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, globs)| *globs)
+        .ok_or_else(|| anyhow!("unknown file type {name}, see --type-list"))
+}
+
+// file name globs match in every directory, as in .gitignore
+pub(crate) fn anchored(glob: &str) -> String {
+    if glob.contains('/') {
+        glob.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{glob}")
+    }
+}
+
+#[derive(Debug, Default)]
+struct Globs {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl Globs {
+    fn new<'a>(globs: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let patterns: Vec<String> = globs.into_iter().map(anchored).collect();
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &patterns {
+            builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+        }
+        Ok(Self {
+            set: builder.build()?,
+            patterns,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+// paths are shown without git's `a/` and `b/` prefixes
+pub(crate) fn strip_prefix<'a>(path: &'a str, prefix: &str) -> &'a str {
+    path.strip_prefix(prefix).unwrap_or(path)
+}
+
+/// The path of the file a statement diffs, without git's `a/` or `b/` prefix:
+/// the old path of deleted files, the new path of every other file.
+pub fn statement_path<'s>(statement: &'s Statement) -> &'s str {
+    if statement.is_deletion() {
+        strip_prefix(&statement.a_file, "a/")
+    } else {
+        strip_prefix(&statement.b_file, "b/")
+    }
+}
+
+fn is_exclude(pathspec: &str) -> bool {
+    [":!", ":^", ":(exclude"]
+        .iter()
+        .any(|magic| pathspec.starts_with(magic))
+}
+
+#[derive(Debug, Default)]
+pub struct PathFilter {
+    globs: Globs,
+    types: Globs,
+    excluded: Globs,
+}
+
+impl PathFilter {
+    /// A filter from `-g` globs, where a leading `!` excludes, and the names
+    /// of the file types to search and not to search.
+    pub fn new(globs: &[String], types: &[String], types_not: &[String]) -> Result<Self> {
+        let (excluded, included): (Vec<&str>, Vec<&str>) = globs
+            .iter()
+            .map(String::as_str)
+            .partition(|g| g.starts_with('!'));
+        let type_globs = |names: &[String]| -> Result<Vec<&str>> {
+            let mut globs = Vec::new();
+            for name in names {
+                globs.extend(type_globs(name)?);
+            }
+            Ok(globs)
+        };
+
+        let mut excluded: Vec<&str> = excluded.iter().map(|g| &g[1..]).collect();
+        excluded.extend(type_globs(types_not)?);
+        Ok(Self {
+            globs: Globs::new(included)?,
+            types: Globs::new(type_globs(types)?)?,
+            excluded: Globs::new(excluded)?,
+        })
+    }
+
+    /// Whether `path`, relative to the repository root, is searched.
+    pub fn is_match(&self, path: &str) -> bool {
+        (self.globs.is_empty() || self.globs.set.is_match(path))
+            && (self.types.is_empty() || self.types.set.is_match(path))
+            && !self.excluded.set.is_match(path)
+    }
+
+    /// Whether the file of `statement` is searched. Deleted files are known by
+    /// their old path, every other file by its new one.
+    pub fn is_statement_match(&self, statement: &Statement) -> bool {
+        self.is_match(statement_path(statement))
+    }
+
+    /// Adds git pathspecs to `pathspecs` so that `git log` and `git diff` skip
+    /// most files the filter rejects. The filter still has to be applied to
+    /// the diffs, since pathspecs can only select the union of their patterns.
+    pub fn push_down(&self, pathspecs: &mut Vec<String>) {
+        // includes would widen the user's own pathspecs instead of narrowing them
+        if pathspecs.iter().all(|p| is_exclude(p)) {
+            let included = if self.globs.is_empty() {
+                &self.types
+            } else {
+                &self.globs
+            };
+            pathspecs.extend(included.patterns.iter().map(|p| format!(":(glob){p}")));
+        }
+        pathspecs.extend(
+            self.excluded
+                .patterns
+                .iter()
+                .map(|p| format!(":(exclude,glob){p}")),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PathFilter;
+
+    fn strings(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn globs() {
+        let filter = PathFilter::new(&strings(&["*.rs", "!src/gen/**"]), &[], &[]).unwrap();
+
+        assert!(filter.is_match("main.rs"));
+        assert!(filter.is_match("src/diff/mod.rs"));
+        assert!(!filter.is_match("src/gen/proto.rs"));
+        assert!(!filter.is_match("README.md"));
+    }
+
+    #[test]
+    fn types() {
+        let filter = PathFilter::new(&[], &strings(&["rust", "toml"]), &[]).unwrap();
+        assert!(filter.is_match("src/lib.rs"));
+        assert!(filter.is_match("Cargo.toml"));
+        assert!(!filter.is_match("web/app.js"));
+
+        let filter = PathFilter::new(&[], &[], &strings(&["js"])).unwrap();
+        assert!(filter.is_match("src/lib.rs"));
+        assert!(!filter.is_match("web/app.mjs"));
+
+        assert!(PathFilter::new(&[], &strings(&["cobol"]), &[]).is_err());
+    }
+
+    #[test]
+    fn globs_and_types_must_both_match() {
+        let filter = PathFilter::new(&strings(&["src/**"]), &strings(&["rust"]), &[]).unwrap();
+
+        assert!(filter.is_match("src/lib.rs"));
+        assert!(!filter.is_match("src/index.html"));
+        assert!(!filter.is_match("benches/parsing.rs"));
+    }
+
+    #[test]
+    fn push_down() {
+        let filter = PathFilter::new(&strings(&["*.rs", "!/vendor/*"]), &[], &[]).unwrap();
+
+        let mut pathspecs = Vec::new();
+        filter.push_down(&mut pathspecs);
+        assert_eq!(pathspecs, [":(glob)**/*.rs", ":(exclude,glob)vendor/*"]);
+
+        let mut pathspecs = strings(&["src/"]);
+        filter.push_down(&mut pathspecs);
+        assert_eq!(pathspecs, ["src/", ":(exclude,glob)vendor/*"]);
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt::Debug;
+
+use aho_corasick::AhoCorasick;
+use anyhow::Result;
+use regex::Regex;
+use regex_syntax::hir::{Hir, HirKind};
+use serde::Serialize;
+
+/// A match in a line: a byte range and the index of the pattern that matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub pattern: usize,
+}
+
+/// Decides which added and removed lines match, and where.
+///
+/// Implement it to search for something other than literals or regexes, and
+/// pass it to `HistorySearch::matcher`.
+pub trait Matcher: Debug + Send + Sync {
+    /// Every match in `line`, in any order. Overlapping spans are merged.
+    fn find_spans(&self, line: &str) -> Vec<Span>;
+
+    fn is_match(&self, line: &str) -> bool {
+        !self.find_spans(line).is_empty()
+    }
+
+    /// Literals of which every matching line contains at least one. They let
+    /// the trigram index skip commits, `None` searches every commit.
+    fn required_literals(&self) -> Option<Vec<String>> {
+        None
+    }
+}
+
+/// Sorts spans and merges overlapping ones, keeping the pattern of the first.
+/// Spans that don't fit in `line` or split a character are dropped.
+pub fn normalize_spans(line: &str, mut spans: Vec<Span>) -> Vec<Span> {
+    spans.retain(|s| {
+        s.start < s.end
+            && s.end <= line.len()
+            && line.is_char_boundary(s.start)
+            && line.is_char_boundary(s.end)
+    });
+    spans.sort();
+
+    let mut merged: Vec<Span> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+/// Matches lines containing a string.
+#[derive(Debug, Clone)]
+pub struct Literal(pub String);
+
+impl Matcher for Literal {
+    fn find_spans(&self, line: &str) -> Vec<Span> {
+        if self.0.is_empty() {
+            return Vec::new();
+        }
+        line.match_indices(self.0.as_str())
+            .map(|(start, m)| Span {
+                start,
+                end: start + m.len(),
+                pattern: 0,
+            })
+            .collect()
+    }
+
+    // an empty literal matches every line
+    fn is_match(&self, line: &str) -> bool {
+        line.contains(self.0.as_str())
+    }
+
+    fn required_literals(&self) -> Option<Vec<String>> {
+        Some(vec![self.0.clone()])
+    }
+}
+
+/// Matches lines containing any of several strings, in a single pass over the line.
+#[derive(Debug, Clone)]
+pub struct MultiLiteral {
+    patterns: Vec<String>,
+    searcher: AhoCorasick,
+}
+
+impl MultiLiteral {
+    pub fn new(patterns: Vec<String>) -> Result<Self> {
+        Ok(Self {
+            searcher: AhoCorasick::new(&patterns)?,
+            patterns,
+        })
+    }
+}
+
+impl Matcher for MultiLiteral {
+    fn find_spans(&self, line: &str) -> Vec<Span> {
+        self.searcher
+            .find_overlapping_iter(line)
+            .filter(|m| !m.is_empty())
+            .map(|m| Span {
+                start: m.start(),
+                end: m.end(),
+                pattern: m.pattern().as_usize(),
+            })
+            .collect()
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        self.searcher.is_match(line)
+    }
+
+    fn required_literals(&self) -> Option<Vec<String>> {
+        Some(self.patterns.clone())
+    }
+}
+
+/// Matches lines matching any of several regular expressions.
+#[derive(Debug, Clone)]
+pub struct RegexMatcher {
+    regexes: Vec<Regex>,
+    required: Option<Vec<String>>,
+}
+
+impl RegexMatcher {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let regexes = patterns
+            .iter()
+            .map(|p| Regex::new(p))
+            .collect::<Result<_, _>>()?;
+        let required = patterns
+            .iter()
+            .map(|p| {
+                regex_syntax::parse(p)
+                    .ok()
+                    .and_then(|hir| required_literals(&hir))
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|literals| literals.concat());
+        Ok(Self { regexes, required })
+    }
+}
+
+// literals of which every match of `hir` contains at least one, or `None` if a
+// match can do without any literal
+fn required_literals(hir: &Hir) -> Option<Vec<String>> {
+    match hir.kind() {
+        HirKind::Literal(literal) => Some(vec![String::from_utf8(literal.0.to_vec()).ok()?]),
+        HirKind::Capture(capture) => required_literals(&capture.sub),
+        HirKind::Repetition(repetition) if repetition.min > 0 => required_literals(&repetition.sub),
+        // any part of a concatenation will do, the one whose shortest literal
+        // is the longest rules out the most commits
+        HirKind::Concat(hirs) => hirs
+            .iter()
+            .filter_map(required_literals)
+            .max_by_key(|literals| literals.iter().map(String::len).min()),
+        HirKind::Alternation(hirs) => hirs
+            .iter()
+            .map(required_literals)
+            .collect::<Option<Vec<_>>>()
+            .map(|literals| literals.concat()),
+        _ => None,
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn find_spans(&self, line: &str) -> Vec<Span> {
+        self.regexes
+            .iter()
+            .enumerate()
+            .flat_map(|(pattern, regex)| {
+                regex.find_iter(line).map(move |m| Span {
+                    start: m.start(),
+                    end: m.end(),
+                    pattern,
+                })
+            })
+            .collect()
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        self.regexes.iter().any(|r| r.is_match(line))
+    }
+
+    fn required_literals(&self) -> Option<Vec<String>> {
+        self.required.clone()
+    }
+}
+
+/// The matcher for `patterns`: literals unless `regex` is set.
+pub fn matcher_for(patterns: &[String], regex: bool) -> Result<Box<dyn Matcher>> {
+    Ok(match patterns {
+        _ if regex => Box::new(RegexMatcher::new(patterns)?),
+        [pattern] => Box::new(Literal(pattern.clone())),
+        _ => Box::new(MultiLiteral::new(patterns.to_vec())?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matcher_for, normalize_spans, Literal, Matcher, MultiLiteral, Span};
+
+    fn spans(matcher: &dyn Matcher, line: &str) -> Vec<(usize, usize, usize)> {
+        normalize_spans(line, matcher.find_spans(line))
+            .into_iter()
+            .map(|s| (s.start, s.end, s.pattern))
+            .collect()
+    }
+
+    fn patterns(p: &[&str]) -> Vec<String> {
+        p.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn literal() {
+        let matcher = Literal("ab".to_string());
+
+        assert_eq!(spans(&matcher, "xabyab"), [(1, 3, 0), (4, 6, 0)]);
+        assert!(!matcher.is_match("a b"));
+        assert!(Literal(String::new()).is_match("anything"));
+    }
+
+    #[test]
+    fn multi_literal_merges_overlaps() {
+        let matcher = MultiLiteral::new(patterns(&["abc", "cd", "zz"])).unwrap();
+
+        assert_eq!(spans(&matcher, "abcd zz"), [(0, 4, 0), (5, 7, 2)]);
+        assert!(!matcher.is_match("ab"));
+        assert_eq!(
+            matcher.required_literals(),
+            Some(patterns(&["abc", "cd", "zz"]))
+        );
+    }
+
+    #[test]
+    fn regex() {
+        let matcher = matcher_for(&patterns(&[r"fn \w+", r"\d+"]), true).unwrap();
+
+        assert_eq!(
+            spans(&*matcher, "fn main() -> u8"),
+            [(0, 7, 0), (14, 15, 1)]
+        );
+        assert!(!matcher.is_match("let x"));
+        assert_eq!(matcher.required_literals(), None);
+        assert!(matcher_for(&patterns(&["("]), true).is_err());
+    }
+
+    #[test]
+    fn required_literals_of_regexes() {
+        let required = |p: &[&str]| matcher_for(&patterns(p), true).unwrap().required_literals();
+
+        assert_eq!(required(&[r"fn \w+"]), Some(patterns(&["fn "])));
+        assert_eq!(
+            required(&[r"^\s*(let|const) [a-z]+_test\b"]),
+            Some(patterns(&["_test"]))
+        );
+        assert_eq!(
+            required(&[r"(foo|bar)+baz?", r"(?:qux){2}"]),
+            Some(patterns(&["foo", "bar", "qux"]))
+        );
+        assert_eq!(required(&[r"unwrap\(\)", r"\d+"]), None);
+        assert_eq!(required(&[r"(?i)unwrap"]), None);
+        assert_eq!(required(&[r"(todo)?"]), None);
+    }
+
+    #[test]
+    fn invalid_spans_are_dropped() {
+        let line = "héllo";
+        let span = |start, end| Span {
+            start,
+            end,
+            pattern: 0,
+        };
+
+        let normalized =
+            normalize_spans(line, vec![span(2, 3), span(0, 1), span(3, 9), span(4, 4)]);
+
+        assert_eq!(normalized, [span(0, 1)]);
+    }
+}
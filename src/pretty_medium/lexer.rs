@@ -1,50 +1,72 @@
-#[derive(Debug, PartialEq)]
-pub enum Token {
-    Hash(String),
-    Word(String),
+#[derive(Debug, PartialEq, Default)]
+pub enum Token<'a> {
+    Hash(&'a str),
+    Word(&'a str),
     Commit,
     Author,
     Date,
+    // the `--pretty=fuller` header lines
+    AuthorDate,
+    Committer,
+    CommitDate,
+    // `Notes:` or `Notes (ref):` at the start of a line, before the notes of a commit
+    Notes,
     NewLine,
     LParen,
     RParen,
-    EOF,
+    Eof,
+    #[default]
     Illegal,
 }
 
-impl Default for Token {
-    fn default() -> Self {
-        Token::Illegal
-    }
-}
-
-impl From<&str> for Token {
-    fn from(s: &str) -> Self {
+impl<'a> From<&'a str> for Token<'a> {
+    fn from(s: &'a str) -> Self {
         match s {
             "commit" => Token::Commit,
             "Author:" => Token::Author,
             "Date:" => Token::Date,
-            s if s.len() == 40 && s.bytes().all(|x| x.is_ascii_hexdigit()) => Token::Hash(s.into()),
-            _ => Token::Word(s.into()),
+            "AuthorDate:" => Token::AuthorDate,
+            "Commit:" => Token::Committer,
+            "CommitDate:" => Token::CommitDate,
+            s if s.len() == 40 && s.bytes().all(|x| x.is_ascii_hexdigit()) => Token::Hash(s),
+            _ => Token::Word(s),
         }
     }
 }
 
-pub struct Lexer {
-    input: Vec<u8>,
+pub struct Lexer<'a> {
+    input: &'a [u8],
     pos: usize,
     ch: u8,
+    // where the last token starts
+    start: usize,
 }
 
-impl Lexer {
-    pub fn new(input: Vec<u8>) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
         Self {
-            ch: input[0],
+            ch: input.first().copied().unwrap_or(b'\0'),
             input,
             pos: 0,
+            start: 0,
         }
     }
 
+    pub fn input(&self) -> &'a [u8] {
+        self.input
+    }
+
+    /// The byte offset of the last token returned by `next_token`.
+    pub fn token_start(&self) -> usize {
+        self.start
+    }
+
+    /// Continues lexing at byte offset `pos`.
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+        self.ch = self.input.get(pos).copied().unwrap_or(b'\0');
+    }
+
     fn read_char(&mut self) {
         if self.pos + 1 >= self.input.len() {
             self.pos += 1;
@@ -61,22 +83,27 @@ impl Lexer {
         }
     }
 
-    fn read_word(&mut self) -> Token {
+    fn read_word(&mut self) -> Token<'a> {
         let s_pos = self.pos;
         while !self.ch.is_ascii_whitespace() && self.ch != b'\0' && self.ch != b')' {
             self.read_char()
         }
-        let word =
-            std::str::from_utf8(&self.input[s_pos..self.pos]).expect("This should be valid ascii");
-        let a = word.into();
-        a
+        // message lines are indented, so a notes header is the only line
+        // starting with `Notes`
+        let line_start = s_pos == 0 || self.input[s_pos - 1] == b'\n';
+        match std::str::from_utf8(&self.input[s_pos..self.pos]) {
+            Ok("Notes:" | "Notes") if line_start => Token::Notes,
+            Ok(word) => word.into(),
+            Err(_) => Token::Illegal,
+        }
     }
 
-    pub fn next_token(&mut self) -> Token {
+    pub fn next_token(&mut self) -> Token<'a> {
         self.skip_whitespace();
+        self.start = self.pos;
 
         let t = match self.ch {
-            b'\0' => Token::EOF,
+            b'\0' => Token::Eof,
             b'(' => Token::LParen,
             b')' => Token::RParen,
             b'\n' => Token::NewLine,
@@ -90,48 +117,37 @@ impl Lexer {
 }
 #[cfg(test)]
 mod tests {
-    impl Lexer {
-        pub fn new_from_string(s: String) -> Self {
-            let input = s.into_bytes();
-            Self {
-                ch: input[0],
-                input,
-                pos: 0,
-            }
-        }
-    }
-
     use super::{Lexer, Token};
-    const PREFIX: &'static str = r#"commit 0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af (HEAD -> master, origin/master)
+    const PREFIX: &str = r#"commit 0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af (HEAD -> master, origin/master)
 Author: Matt Christofides <matt.christofides@gmail.com>
 Date:   Sat Nov 25 15:58:03 2023 -0500
 
 "#;
 
-    fn gen_prefix_matches() -> Vec<Token> {
+    fn gen_prefix_matches() -> Vec<Token<'static>> {
         use Token::*;
         vec![
             Commit,
-            Hash("0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af".into()),
+            Hash("0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af"),
             LParen,
-            Word("HEAD".into()),
-            Word("->".into()),
-            Word("master,".into()),
-            Word("origin/master".into()),
+            Word("HEAD"),
+            Word("->"),
+            Word("master,"),
+            Word("origin/master"),
             RParen,
             NewLine,
             Author,
-            Word("Matt".into()),
-            Word("Christofides".into()),
-            Word("<matt.christofides@gmail.com>".into()),
+            Word("Matt"),
+            Word("Christofides"),
+            Word("<matt.christofides@gmail.com>"),
             NewLine,
             Date,
-            Word("Sat".into()),
-            Word("Nov".into()),
-            Word("25".into()),
-            Word("15:58:03".into()),
-            Word("2023".into()),
-            Word("-0500".into()),
+            Word("Sat"),
+            Word("Nov"),
+            Word("25"),
+            Word("15:58:03"),
+            Word("2023"),
+            Word("-0500"),
             NewLine,
             NewLine,
         ]
@@ -143,17 +159,17 @@ Date:   Sat Nov 25 15:58:03 2023 -0500
         use Token::*;
         let mut output = gen_prefix_matches();
         output.append(&mut vec![
-            Word("HEAD".into()),
+            Word("HEAD"),
             Commit,
             Author,
             Date,
-            Word("test".into()),
-            EOF,
+            Word("test"),
+            Eof,
         ]);
 
-        let mut l = Lexer::new_from_string(input.into());
-        for i in 0..output.len() {
-            assert_eq!(output[i], l.next_token())
+        let mut l = Lexer::new(input.as_bytes());
+        for out in output {
+            assert_eq!(out, l.next_token())
         }
     }
 
@@ -168,29 +184,29 @@ Author: Matt Christofides <matt.christofides@gmail.com>"#
         use Token::*;
         let mut output = gen_prefix_matches();
         output.append(&mut vec![
-            Word("feat:".into()),
-            Word("added".into()),
-            Word("target".into()),
-            Word("dir".into()),
-            Word("option".into()),
+            Word("feat:"),
+            Word("added"),
+            Word("target"),
+            Word("dir"),
+            Word("option"),
             NewLine,
             NewLine,
             Commit,
-            Hash("bb4055c04da174bbfc93e63952d4ccc84e4832ab".into()),
+            Hash("bb4055c04da174bbfc93e63952d4ccc84e4832ab"),
             LParen,
-            Word("origin/master".into()),
+            Word("origin/master"),
             RParen,
             NewLine,
             Author,
-            Word("Matt".into()),
-            Word("Christofides".into()),
-            Word("<matt.christofides@gmail.com>".into()),
-            EOF,
+            Word("Matt"),
+            Word("Christofides"),
+            Word("<matt.christofides@gmail.com>"),
+            Eof,
         ]);
 
-        let mut l = Lexer::new_from_string(input.into());
-        for i in 0..output.len() {
-            assert_eq!(output[i], l.next_token())
+        let mut l = Lexer::new(input.as_bytes());
+        for out in output {
+            assert_eq!(out, l.next_token())
         }
     }
 }
@@ -6,35 +6,163 @@ pub struct Program(pub Vec<Commit>, pub Vec<Error>);
 #[derive(Debug, Clone)]
 pub struct Commit {
     pub hash: String,
+    // only filled in when the log is generated with `--parents`
+    pub parents: Vec<String>,
+    /// `Name <email>`
+    pub author: String,
+    /// `Name <email>`, only filled in when the log is generated with `--pretty=fuller`
+    pub committer: String,
+    /// the author date
     pub date: String,
+    /// only filled in when the log is generated with `--pretty=fuller`
+    pub committer_date: String,
     pub message: String,
+    /// what the commit is shown as when it isn't a commit of the log, e.g.
+    /// `main...HEAD` for the net diff of a branch
+    pub label: Option<String>,
+    /// set for the changes that aren't committed yet, which have no hash
+    pub uncommitted: Option<Uncommitted>,
+    /// the commit's notes, only filled in when the log is generated with
+    /// `--notes`, and the annotations of the tags pointing to it
+    pub annotations: Vec<Annotation>,
     #[allow(dead_code)]
     head: Option<Vec<String>>,
 }
 
-pub struct Parser {
-    l: Lexer,
-    curr_token: Token,
-    peek_token: Token,
-}
+impl Commit {
+    /// A commit that isn't in the log: the diff between `parent` and `hash`,
+    /// shown as `label`.
+    pub fn labelled(label: String, hash: String, parent: String, message: String) -> Self {
+        Self {
+            hash,
+            parents: vec![parent],
+            author: String::new(),
+            committer: String::new(),
+            date: String::new(),
+            committer_date: String::new(),
+            message,
+            label: Some(label),
+            uncommitted: None,
+            annotations: Vec::new(),
+            head: None,
+        }
+    }
 
-impl Parser {
-    pub fn new(mut l: Lexer) -> Self {
-        let curr_token = l.next_token();
-        let peek_token = l.next_token();
+    /// The `uncommitted` changes on top of `head`, which stands in for their
+    /// hash and parent.
+    pub fn uncommitted(uncommitted: Uncommitted, head: String) -> Self {
+        let (label, message) = match uncommitted {
+            Uncommitted::Staged => ("staged", "the changes staged for the next commit"),
+            Uncommitted::Unstaged => ("worktree", "the changes in the worktree that aren't staged"),
+        };
         Self {
-            l,
-            curr_token,
-            peek_token,
+            uncommitted: Some(uncommitted),
+            ..Self::labelled(label.to_string(), head.clone(), head, message.to_string())
+        }
+    }
+
+    /// The text of every note, on a single line.
+    pub fn notes(&self) -> String {
+        self.annotations
+            .iter()
+            .filter(|a| a.kind == AnnotationKind::Note)
+            .flat_map(|a| a.text.split_whitespace())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Text attached to a commit outside of its message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub kind: AnnotationKind,
+    /// the tag's name, or the ref the note is from, empty for the default
+    /// notes ref
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnnotationKind {
+    /// the message of an annotated tag
+    Tag,
+    /// a note from `refs/notes/`
+    Note,
+}
+
+/// Changes that aren't committed yet, searched like a commit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Uncommitted {
+    /// `git diff --cached`
+    Staged,
+    /// `git diff`
+    Unstaged,
+}
+
+impl Uncommitted {
+    /// The arguments of `git diff` that show the changes.
+    pub fn diff_args(self) -> &'static [&'static str] {
+        match self {
+            Uncommitted::Staged => &["--cached"],
+            Uncommitted::Unstaged => &[],
         }
     }
+}
+
+/// Splits a `Name <email>` identity into the name and the email.
+pub fn split_identity(identity: &str) -> (&str, &str) {
+    match identity.rsplit_once(" <") {
+        Some((name, email)) => (name, email.trim_end_matches('>')),
+        None => (identity, ""),
+    }
+}
+
+pub struct Parser<'a> {
+    l: Lexer<'a>,
+    curr_token: Token<'a>,
+    peek_token: Token<'a>,
+    // byte offsets of the tokens in the input
+    curr_start: usize,
+    peek_start: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(l: Lexer<'a>) -> Self {
+        let mut p = Self {
+            l,
+            curr_token: Token::Eof,
+            peek_token: Token::Eof,
+            curr_start: 0,
+            peek_start: 0,
+        };
+        p.seek(0);
+        p
+    }
 
     fn next_token(&mut self) {
         self.curr_token = std::mem::take(&mut self.peek_token);
+        self.curr_start = self.peek_start;
         self.peek_token = self.l.next_token();
+        self.peek_start = self.l.token_start();
     }
 
-    fn expect_token(&mut self, t: Token) {
+    // continues with the first token at or after byte offset `pos`
+    fn seek(&mut self, pos: usize) {
+        self.l.seek(pos);
+        self.next_token();
+        self.next_token();
+    }
+
+    // the offset of the end of the line at `pos`, before its newline
+    fn line_end(&self, pos: usize) -> usize {
+        let input = self.l.input();
+        input[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(input.len(), |i| pos + i)
+    }
+
+    fn expect_token(&mut self, t: Token<'a>) {
         if self.curr_token != t {
             panic!("{:?},{:?}", t, self.curr_token);
             // return Err(anyhow::anyhow!("token does not match: {t:?}"));
@@ -48,63 +176,116 @@ impl Parser {
         }
     }
 
-    fn skip_until(&mut self, t: Token) {
-        while self.curr_token != t && t != Token::EOF {
+    fn skip_until(&mut self, t: Token<'a>) {
+        while self.curr_token != t && self.curr_token != Token::Eof {
             self.next_token();
         }
     }
 
     pub fn parse_section(&mut self) -> Result<Commit> {
         self.expect_token(Token::Commit);
-        let Token::Hash(ref hash) = self.curr_token else {
+        let Token::Hash(hash) = self.curr_token else {
             panic!("First token should be a hash, {:?}", self.curr_token);
         };
-        let hash = hash.clone();
         self.next_token();
 
+        let mut parents = Vec::new();
+        while let Token::Hash(parent) = self.curr_token {
+            parents.push(parent.to_string());
+            self.next_token();
+        }
+
         let mut head = None;
         if self.curr_token == Token::LParen {
             head = Some(self.get_head());
         }
-        self.skip_until(Token::Date);
-        let date = self.get_date();
+        self.skip_until(Token::Author);
+        let author = self.get_field();
+        self.skip_newlines();
+        let mut committer = String::new();
+        let mut committer_date = String::new();
+        let date = if self.curr_token == Token::AuthorDate {
+            let date = self.get_field();
+            self.skip_until(Token::Committer);
+            committer = self.get_field();
+            self.skip_until(Token::CommitDate);
+            committer_date = self.get_field();
+            date
+        } else {
+            self.skip_until(Token::Date);
+            self.get_field()
+        };
 
         self.skip_newlines();
 
-        let mut message: Vec<String> = Vec::new(); // TODO: change this to a vec
-        loop {
-            match self.curr_token {
-                Token::Word(ref s) => message.push(s.to_string()),
-                Token::EOF => break,
-                Token::Commit => {
-                    // this checks if there is the word "commit" in the commit message
-                    if let Token::Hash(_) = &self.peek_token {
-                        break;
-                    }
-                    message.push("commit".to_string());
-                }
-                Token::NewLine => message.push("\n".to_string()),
-                Token::Author => message.push("Author:".to_string()),
-                Token::Date => message.push("Date:".to_string()),
-                _ => panic!("this token should not be here... {:?}", self.curr_token),
-            }
-            self.next_token();
+        let message = self.get_text();
+        let mut annotations = Vec::new();
+        while self.curr_token == Token::Notes {
+            // `Notes:` or `Notes (ref):`
+            let end = self.line_end(self.curr_start);
+            let header = String::from_utf8_lossy(&self.l.input()[self.curr_start..end]);
+            let name = header["Notes".len()..].trim().trim_end_matches(':');
+            let name = name
+                .strip_prefix('(')
+                .and_then(|n| n.strip_suffix(')'))
+                .unwrap_or(name)
+                .to_string();
+            self.seek(end);
+            self.next_token(); // move past the newline
+            annotations.push(Annotation {
+                kind: AnnotationKind::Note,
+                name,
+                text: self.get_text(),
+            });
         }
         Ok(Commit {
-            hash,
+            hash: hash.to_string(),
+            parents,
+            author,
+            committer,
             head,
-            message: message.join(" ").trim().into(),
+            message,
             date,
+            committer_date,
+            label: None,
+            uncommitted: None,
+            annotations,
         })
     }
 
+    // the indented lines from the current one on, e.g. a commit message,
+    // without their indentation
+    fn get_text(&mut self) -> String {
+        let input = self.l.input();
+        let mut pos = input[..self.curr_start]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let mut lines = Vec::new();
+        while pos < input.len() {
+            let end = self.line_end(pos);
+            let line = &input[pos..end];
+            if let Some(line) = line.strip_prefix(b"    ") {
+                lines.push(String::from_utf8_lossy(line));
+            } else if line.iter().all(u8::is_ascii_whitespace) {
+                lines.push("".into());
+            } else {
+                // the next commit or its notes
+                break;
+            }
+            pos = end + 1;
+        }
+        self.seek(pos.min(input.len()));
+        lines.join("\n").trim().to_string()
+    }
+
     pub fn get_head(&mut self) -> Vec<String> {
         let mut v = Vec::new();
         self.next_token(); // move past the LParen that brought us here
 
         loop {
-            match &self.curr_token {
-                Token::Word(w) => v.push(w.clone()),
+            match self.curr_token {
+                Token::Word(w) => v.push(w.to_string()),
                 Token::RParen => break,
                 _ => {}
             }
@@ -115,25 +296,27 @@ impl Parser {
         v
     }
 
-    fn get_date(&mut self) -> String {
-        self.next_token(); // move past the date keyword
-        let mut out = Vec::new();
-        loop {
-            match &self.curr_token {
-                Token::Word(w) => out.push(w.clone()),
-                Token::NewLine | Token::EOF => break,
-                _ => {}
-            }
-            self.next_token();
-        }
-        out.join(" ")
+    // the rest of a header line like `Author:` or `Date:`, as written
+    fn get_field(&mut self) -> String {
+        let input = self.l.input();
+        let end = self.line_end(self.curr_start);
+        // move past the keyword
+        let start = input[self.curr_start..end]
+            .iter()
+            .position(u8::is_ascii_whitespace)
+            .map_or(end, |i| self.curr_start + i);
+        let field = String::from_utf8_lossy(&input[start..end])
+            .trim()
+            .to_string();
+        self.seek(end);
+        field
     }
 
     pub fn parse_program(&mut self) -> Program {
         let mut commits = Vec::new();
         let mut errors = Vec::new();
 
-        while self.curr_token != Token::EOF {
+        while self.curr_token != Token::Eof {
             match self.parse_section() {
                 Ok(line) => commits.push(line),
                 Err(err) => errors.push(err),
@@ -146,13 +329,18 @@ impl Parser {
 
 #[cfg(test)]
 mod tests {
-    use super::{Commit, Lexer, Parser, Program};
+    use super::{split_identity, Annotation, AnnotationKind, Commit, Lexer, Parser, Program};
 
     fn compare_commits(a: &Commit, b: &Commit) {
         assert_eq!(a.hash, b.hash);
+        assert_eq!(a.parents, b.parents);
+        assert_eq!(a.author, b.author);
+        assert_eq!(a.committer, b.committer);
         assert_eq!(a.head, b.head);
         assert_eq!(a.message, b.message);
         assert_eq!(a.date, b.date);
+        assert_eq!(a.committer_date, b.committer_date);
+        assert_eq!(a.annotations, b.annotations);
     }
 
     fn compare_programs(a: &Program, b: Vec<Commit>) {
@@ -168,22 +356,29 @@ mod tests {
 Author: Matt Christofides <matt.christofides@gmail.com>
 Date:   Sat Nov 25 17:52:39 2023 -0500
 
-    HEAD commit Author: Date: testing
+    HEAD commit Author: Date: testing (parens)
     "#;
 
         let commits = vec![Commit {
             hash: "bb4055c04da174bbfc93e63952d4ccc84e4832ab".to_string(),
+            parents: vec![],
+            author: "Matt Christofides <matt.christofides@gmail.com>".to_string(),
+            committer: String::new(),
             head: Some(vec![
                 "HEAD".to_string(),
                 "->".to_string(),
                 "master,".to_string(),
                 "origin/master".to_string(),
             ]),
-            message: "HEAD commit Author: Date: testing".to_string(),
+            message: "HEAD commit Author: Date: testing (parens)".to_string(),
             date: "Sat Nov 25 17:52:39 2023 -0500".to_string(),
+            committer_date: String::new(),
+            label: None,
+            uncommitted: None,
+            annotations: Vec::new(),
         }];
 
-        let l = Lexer::new(input.to_string().into_bytes());
+        let l = Lexer::new(input.as_bytes());
         let mut p = Parser::new(l);
         let program = p.parse_program();
 
@@ -214,6 +409,9 @@ Date:   Sat Nov 25 15:58:03 2023 -0500
         let commits = vec![
             Commit {
                 hash: "ebcbf7f96d2c6690e43833e60345075ce752bef0".to_string(),
+                parents: vec![],
+                author: "Matt Christofides <matt.christofides@gmail.com>".to_string(),
+                committer: String::new(),
                 head: Some(vec![
                     "HEAD".to_string(),
                     "->".to_string(),
@@ -221,26 +419,251 @@ Date:   Sat Nov 25 15:58:03 2023 -0500
                 ]),
                 message: "feat: added date to matched commit output".to_string(),
                 date: "Sat Nov 25 22:56:43 2023 -0500".to_string(),
+                committer_date: String::new(),
+                label: None,
+                uncommitted: None,
+                annotations: Vec::new(),
             },
             Commit {
                 hash: "bb4055c04da174bbfc93e63952d4ccc84e4832ab".to_string(),
+                parents: vec![],
+                author: "Matt Christofides <matt.christofides@gmail.com>".to_string(),
+                committer: String::new(),
                 head: Some(vec!["origin/master".to_string()]),
                 message:
-                    "feat: added parser for git log --pretty=medium as we want the date \n included"
+                    "feat: added parser for git log --pretty=medium as we want the date\nincluded"
                         .to_string(),
                 date: "Sat Nov 25 17:52:39 2023 -0500".to_string(),
+                committer_date: String::new(),
+                label: None,
+                uncommitted: None,
+                annotations: Vec::new(),
+            },
+            Commit {
+                hash: "0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af".to_string(),
+                parents: vec![],
+                author: "Matt Christofides <matt.christofides@gmail.com>".to_string(),
+                committer: String::new(),
+                head: None,
+                message: "feat: added target dir option".to_string(),
+                date: "Sat Nov 25 15:58:03 2023 -0500".to_string(),
+                committer_date: String::new(),
+                label: None,
+                uncommitted: None,
+                annotations: Vec::new(),
+            },
+        ];
+
+        let l = Lexer::new(input.as_bytes());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        compare_programs(&program, commits);
+    }
+
+    #[test]
+    fn parsing_parents() {
+        let input = r#"commit ebcbf7f96d2c6690e43833e60345075ce752bef0 bb4055c04da174bbfc93e63952d4ccc84e4832ab 0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af (HEAD -> master)
+Merge: bb4055c 0b5a4e8
+Author: Matt Christofides <matt.christofides@gmail.com>
+Date:   Sat Nov 25 22:56:43 2023 -0500
+
+    Merge branch 'feature'
+
+commit 0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af
+Author: Matt Christofides <matt.christofides@gmail.com>
+Date:   Sat Nov 25 15:58:03 2023 -0500
+
+    feat: added target dir option"#;
+
+        let commits = vec![
+            Commit {
+                hash: "ebcbf7f96d2c6690e43833e60345075ce752bef0".to_string(),
+                parents: vec![
+                    "bb4055c04da174bbfc93e63952d4ccc84e4832ab".to_string(),
+                    "0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af".to_string(),
+                ],
+                author: "Matt Christofides <matt.christofides@gmail.com>".to_string(),
+                committer: String::new(),
+                head: Some(vec![
+                    "HEAD".to_string(),
+                    "->".to_string(),
+                    "master".to_string(),
+                ]),
+                message: "Merge branch 'feature'".to_string(),
+                date: "Sat Nov 25 22:56:43 2023 -0500".to_string(),
+                committer_date: String::new(),
+                label: None,
+                uncommitted: None,
+                annotations: Vec::new(),
             },
             Commit {
                 hash: "0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af".to_string(),
+                parents: vec![],
+                author: "Matt Christofides <matt.christofides@gmail.com>".to_string(),
+                committer: String::new(),
                 head: None,
                 message: "feat: added target dir option".to_string(),
                 date: "Sat Nov 25 15:58:03 2023 -0500".to_string(),
+                committer_date: String::new(),
+                label: None,
+                uncommitted: None,
+                annotations: Vec::new(),
             },
         ];
 
-        let l = Lexer::new(input.to_string().into_bytes());
+        let l = Lexer::new(input.as_bytes());
         let mut p = Parser::new(l);
         let program = p.parse_program();
         compare_programs(&program, commits);
     }
+
+    #[test]
+    fn parsing_pretty_fuller() {
+        let input = r#"commit ebcbf7f96d2c6690e43833e60345075ce752bef0 0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af
+Author:     Matt Christofides <matt.christofides@gmail.com>
+AuthorDate: Sat Nov 25 22:56:43 2023 -0500
+Commit:     Jane Doe <jane@example.com>
+CommitDate: Mon Nov 27 09:12:00 2023 +0100
+
+    fix: Commit: and CommitDate: in a message
+"#;
+
+        let commits = vec![Commit {
+            hash: "ebcbf7f96d2c6690e43833e60345075ce752bef0".to_string(),
+            parents: vec!["0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af".to_string()],
+            author: "Matt Christofides <matt.christofides@gmail.com>".to_string(),
+            committer: "Jane Doe <jane@example.com>".to_string(),
+            head: None,
+            message: "fix: Commit: and CommitDate: in a message".to_string(),
+            date: "Sat Nov 25 22:56:43 2023 -0500".to_string(),
+            committer_date: "Mon Nov 27 09:12:00 2023 +0100".to_string(),
+            label: None,
+            uncommitted: None,
+            annotations: Vec::new(),
+        }];
+
+        let l = Lexer::new(input.as_bytes());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        compare_programs(&program, commits);
+    }
+
+    #[test]
+    fn parsing_non_ascii_identities() {
+        let input = "commit ebcbf7f96d2c6690e43833e60345075ce752bef0
+Author:     Émile Zola <emile@example.com>
+AuthorDate: Sat Nov 25 22:56:43 2023 -0500
+Commit:     Łukasz Nowak <lukasz@example.com>
+CommitDate: Mon Nov 27 09:12:00 2023 +0100
+
+    fix: typo
+";
+
+        let l = Lexer::new(input.as_bytes());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        assert_eq!(program.0[0].author, "Émile Zola <emile@example.com>");
+        assert_eq!(program.0[0].committer, "Łukasz Nowak <lukasz@example.com>");
+        assert_eq!(program.0[0].date, "Sat Nov 25 22:56:43 2023 -0500");
+        assert_eq!(program.0[0].message, "fix: typo");
+    }
+
+    #[test]
+    fn parsing_raw_message() {
+        let input = "commit ebcbf7f96d2c6690e43833e60345075ce752bef0
+Author: Matt Christofides <matt.christofides@gmail.com>
+Date:   Sat Nov 25 22:56:43 2023 -0500
+
+    feat(api): add   héllo, 世界
+
+    Ünïcode body (with parens)
+";
+
+        let l = Lexer::new(input.as_bytes());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        assert_eq!(program.0.len(), 1);
+        assert_eq!(
+            program.0[0].message,
+            "feat(api): add   héllo, 世界\n\nÜnïcode body (with parens)"
+        );
+    }
+
+    #[test]
+    fn parsing_notes() {
+        let input = r#"commit ebcbf7f96d2c6690e43833e60345075ce752bef0
+Author:     Matt Christofides <matt.christofides@gmail.com>
+AuthorDate: Sat Nov 25 22:56:43 2023 -0500
+Commit:     Matt Christofides <matt.christofides@gmail.com>
+CommitDate: Sat Nov 25 22:56:43 2023 -0500
+
+    fix: Notes: in a message
+
+Notes:
+    reviewed by bob
+
+Notes (review):
+    LGTM
+
+commit 0b5a4e8d5a1ae5b6d5539e3fc7023e0f3faf77af
+Author:     Matt Christofides <matt.christofides@gmail.com>
+AuthorDate: Sat Nov 25 15:58:03 2023 -0500
+Commit:     Matt Christofides <matt.christofides@gmail.com>
+CommitDate: Sat Nov 25 15:58:03 2023 -0500
+
+    first
+"#;
+        let note = |name: &str, text: &str| Annotation {
+            kind: AnnotationKind::Note,
+            name: name.to_string(),
+            text: text.to_string(),
+        };
+
+        let l = Lexer::new(input.as_bytes());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        assert_eq!(program.0[0].message, "fix: Notes: in a message");
+        assert_eq!(
+            program.0[0].annotations,
+            [note("", "reviewed by bob"), note("review", "LGTM")]
+        );
+        assert_eq!(program.0[1].message, "first");
+        assert!(program.0[1].annotations.is_empty());
+    }
+
+    #[test]
+    fn parsing_raw_notes() {
+        let input = "commit ebcbf7f96d2c6690e43833e60345075ce752bef0
+Author: Matt Christofides <matt.christofides@gmail.com>
+Date:   Sat Nov 25 22:56:43 2023 -0500
+
+    fix: typo
+
+Notes (révision):
+    Überprüft von Zoë (siehe   #12)
+    → OK
+";
+
+        let l = Lexer::new(input.as_bytes());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        assert_eq!(program.0.len(), 1);
+        assert_eq!(
+            program.0[0].annotations,
+            [Annotation {
+                kind: AnnotationKind::Note,
+                name: "révision".to_string(),
+                text: "Überprüft von Zoë (siehe   #12)\n→ OK".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn identities() {
+        assert_eq!(
+            split_identity("Jane Doe <jane@example.com>"),
+            ("Jane Doe", "jane@example.com")
+        );
+        assert_eq!(split_identity("nobody"), ("nobody", ""));
+    }
 }
@@ -0,0 +1,100 @@
+use std::io::BufRead;
+
+use anyhow::Result;
+
+use super::{
+    lexer::Lexer,
+    parser::{Commit, Parser},
+};
+
+/// Parses `git log --pretty=medium` output incrementally, yielding one
+/// `Commit` at a time. Only the commit currently being parsed is buffered.
+pub struct CommitReader<R: BufRead> {
+    reader: R,
+    buf: Vec<u8>,
+    // `commit <hash>` line that ended the previous commit
+    next_header: Vec<u8>,
+}
+
+// commit messages are indented, so only a header can start with `commit <hash>`
+fn is_commit_header(line: &[u8]) -> bool {
+    line.starts_with(b"commit ")
+        && line.len() >= 47
+        && line[7..47].iter().all(|b| b.is_ascii_hexdigit())
+}
+
+impl<R: BufRead> CommitReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            next_header: Vec::new(),
+        }
+    }
+
+    fn fill_commit(&mut self) -> Result<()> {
+        self.buf.clear();
+        self.buf.append(&mut self.next_header);
+        loop {
+            let line_start = self.buf.len();
+            if self.reader.read_until(b'\n', &mut self.buf)? == 0 {
+                return Ok(());
+            }
+            if line_start > 0 && is_commit_header(&self.buf[line_start..]) {
+                self.next_header = self.buf.split_off(line_start);
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CommitReader<R> {
+    type Item = Result<Commit>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.fill_commit() {
+            return Some(Err(e));
+        }
+        if self.buf.is_empty() {
+            return None;
+        }
+        Some(Parser::new(Lexer::new(&self.buf)).parse_section())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommitReader;
+
+    #[test]
+    fn reads_commits_one_at_a_time() {
+        let input = r#"commit ebcbf7f96d2c6690e43833e60345075ce752bef0 bb4055c04da174bbfc93e63952d4ccc84e4832ab (HEAD -> master)
+Author: Matt Christofides <matt.christofides@gmail.com>
+Date:   Sat Nov 25 22:56:43 2023 -0500
+
+    feat: added date to matched commit output
+
+commit bb4055c04da174bbfc93e63952d4ccc84e4832ab
+Author: Matt Christofides <matt.christofides@gmail.com>
+Date:   Sat Nov 25 17:52:39 2023 -0500
+
+    feat: added parser for git log --pretty=medium
+"#;
+
+        let commits = CommitReader::new(input.as_bytes())
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(commits.len(), 2);
+        assert_eq!(commits[0].hash, "ebcbf7f96d2c6690e43833e60345075ce752bef0");
+        assert_eq!(
+            commits[0].parents,
+            vec!["bb4055c04da174bbfc93e63952d4ccc84e4832ab".to_string()]
+        );
+        assert_eq!(
+            commits[1].message,
+            "feat: added parser for git log --pretty=medium"
+        );
+        assert!(commits[1].parents.is_empty());
+    }
+}
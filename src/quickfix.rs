@@ -0,0 +1,187 @@
+//! `rev:path:line:col:text` output for editors' quickfix lists, and the files
+//! to jump to.
+//!
+//! A match's `rev` is the revision that has the line: the commit for added
+//! lines and its parent for removed ones. Lines of staged changes are in the
+//! index, written `:path:line:col:text` like git names them, and added lines
+//! of unstaged changes are in the worktree, written without a `rev`.
+//! `materialize` writes that revision of the file to a temporary file, where
+//! `line` is the matched line, or for the worktree returns the file itself.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    diff::diff_ast::ContentType,
+    git::git,
+    matcher::{CommitMatcher, DiffLine, FileMatches},
+    paths::strip_prefix,
+    sink::{Sink, Summary},
+};
+
+/// Writes a line per match, like `rg --vimgrep`. Columns are 1-based byte
+/// offsets of the match.
+pub struct QuickfixSink<W: Write> {
+    out: W,
+    // the revisions and file the following lines belong to, see
+    // `CommitMatcher::revision`
+    old_rev: Option<String>,
+    new_rev: Option<String>,
+    old_path: String,
+    new_path: String,
+}
+
+impl<W: Write> QuickfixSink<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            old_rev: None,
+            new_rev: None,
+            old_path: String::new(),
+            new_path: String::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Sink for QuickfixSink<W> {
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()> {
+        self.old_rev = commit.revision(&ContentType::Remove).map(str::to_string);
+        self.new_rev = commit.revision(&ContentType::Add).map(str::to_string);
+        Ok(())
+    }
+
+    fn file(&mut self, file: &FileMatches) -> io::Result<()> {
+        self.old_path = strip_prefix(file.file_a(), "a/").to_string();
+        self.new_path = strip_prefix(file.file_b(), "b/").to_string();
+        Ok(())
+    }
+
+    fn match_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        let (rev, path, number) = match line.side() {
+            ContentType::Remove => (&self.old_rev, &self.old_path, line.old_line()),
+            _ => (&self.new_rev, &self.new_path, line.new_line()),
+        };
+        let file = match rev {
+            Some(rev) => format!("{rev}:{path}"),
+            None => path.clone(),
+        };
+        let number = number.unwrap_or(1);
+        if line.spans().is_empty() {
+            return writeln!(self.out, "{file}:{number}:1:{}", line.text());
+        }
+        for span in line.spans() {
+            writeln!(
+                self.out,
+                "{file}:{number}:{}:{}",
+                span.start + 1,
+                line.text()
+            )?;
+        }
+        Ok(())
+    }
+
+    fn context_line(&mut self, _line: &DiffLine) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end_commit(&mut self, _commit: &CommitMatcher) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn end(&mut self, _summary: &Summary) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Writes `path` as it is in `rev` to a temporary file and returns its path.
+/// `spec` is `rev:path`, the start of a quickfix line, with an empty `rev` for
+/// the index; anything after the path is ignored. The file keeps its name, so
+/// editors still recognize its type. A quickfix line without a `rev` is in the
+/// worktree, and its file is returned as it is.
+pub fn materialize(target_dir: Option<&str>, spec: &str) -> Result<PathBuf> {
+    let path = quickfix_path(spec);
+    if path != spec && !path.contains(':') {
+        check_relative(path)?;
+        let root = git(target_dir, &["rev-parse", "--show-toplevel"])?;
+        let file = Path::new(String::from_utf8(root)?.trim()).join(path);
+        if !file.is_file() {
+            bail!("{path} is not in the worktree");
+        }
+        return Ok(file);
+    }
+
+    let (rev, path) = spec
+        .split_once(':')
+        .ok_or_else(|| anyhow!("expected rev:path, got {spec}"))?;
+    let path = quickfix_path(path);
+    check_relative(path)?;
+
+    // `:path` is the file in the index
+    let hash = if rev.is_empty() {
+        String::new()
+    } else {
+        let hash = git(
+            target_dir,
+            &[
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("{rev}^{{commit}}"),
+            ],
+        )
+        .map_err(|_| anyhow!("{rev} is not a commit"))?;
+        String::from_utf8(hash)?.trim().to_string()
+    };
+    let content = git(target_dir, &["show", &format!("{hash}:{path}")])?;
+
+    let dir = if hash.is_empty() { "index" } else { &hash };
+    let file = std::env::temp_dir().join("git_grep").join(dir).join(path);
+    fs::create_dir_all(file.parent().expect("the path has a file name"))?;
+    fs::write(&file, content)?;
+    Ok(file)
+}
+
+fn check_relative(path: &str) -> Result<()> {
+    if !Path::new(path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        bail!("{path} must be a relative path inside the repository");
+    }
+    Ok(())
+}
+
+// `path` without the `:line:col:text` of a quickfix line
+fn quickfix_path(rest: &str) -> &str {
+    for (idx, _) in rest.match_indices(':') {
+        let after = &rest[idx + 1..];
+        let digits = after.bytes().take_while(u8::is_ascii_digit).count();
+        if digits > 0 && (digits == after.len() || after.as_bytes()[digits] == b':') {
+            return &rest[..idx];
+        }
+    }
+    rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::quickfix_path;
+
+    #[test]
+    fn paths_of_quickfix_lines() {
+        assert_eq!(quickfix_path("src/main.rs"), "src/main.rs");
+        assert_eq!(quickfix_path("src/main.rs:12:5:let x = 1;"), "src/main.rs");
+        assert_eq!(quickfix_path("src/main.rs:12"), "src/main.rs");
+        assert_eq!(quickfix_path("odd:name.rs"), "odd:name.rs");
+        assert_eq!(quickfix_path("odd:name.rs:3:1:x"), "odd:name.rs");
+    }
+}
@@ -0,0 +1,262 @@
+//! SARIF 2.1.0 output, for code scanning tools.
+//!
+//! Every pattern is a rule and every match a result, located at the line in
+//! the revision that has it: the commit for added lines, its parent for removed
+//! ones, the index or the worktree for uncommitted changes. The commit, its
+//! notes and the revision are in the result's `properties`.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::{
+    diff::diff_ast::ContentType,
+    matcher::{CommitMatcher, DiffLine, FileMatches},
+    paths::strip_prefix,
+    pattern::Span,
+    sink::{Sink, Summary},
+};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+#[derive(Serialize)]
+struct Message<'a> {
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule<'a> {
+    id: String,
+    name: String,
+    short_description: Message<'a>,
+}
+
+fn rule_id(pattern: usize) -> String {
+    format!("pattern/{pattern}")
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver<'a> {
+    name: &'static str,
+    version: &'static str,
+    rules: Vec<Rule<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region<'a> {
+    start_line: usize,
+    start_column: usize,
+    end_column: usize,
+    snippet: Message<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactLocation<'a> {
+    uri: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation<'a> {
+    artifact_location: ArtifactLocation<'a>,
+    region: Region<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location<'a> {
+    physical_location: PhysicalLocation<'a>,
+}
+
+#[derive(Serialize)]
+struct Properties<'a> {
+    commit: &'a str,
+    parent: &'a str,
+    /// the revision the line is in, empty for the index and left out for the
+    /// worktree
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revision: Option<&'a str>,
+    side: &'static str,
+    date: &'a str,
+    message: &'a str,
+    notes: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult<'a> {
+    rule_id: String,
+    rule_index: usize,
+    level: &'static str,
+    message: Message<'a>,
+    locations: [Location<'a>; 1],
+    properties: Properties<'a>,
+}
+
+/// Writes the results as a SARIF log with a single run. Results are written as
+/// soon as they're found, so the log is only complete once the search is.
+pub struct SarifSink<W: Write> {
+    out: W,
+    patterns: Vec<String>,
+    first_result: bool,
+    // the commit and files the following lines belong to
+    hash: String,
+    parent: String,
+    // see `CommitMatcher::revision`
+    old_rev: Option<String>,
+    new_rev: Option<String>,
+    date: String,
+    message: String,
+    notes: String,
+    old_path: String,
+    new_path: String,
+}
+
+impl<W: Write> SarifSink<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            patterns: Vec::new(),
+            first_result: true,
+            hash: String::new(),
+            parent: String::new(),
+            old_rev: None,
+            new_rev: None,
+            date: String::new(),
+            message: String::new(),
+            notes: String::new(),
+            old_path: String::new(),
+            new_path: String::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn result(&mut self, line: &DiffLine, span: Option<&Span>) -> io::Result<()> {
+        if !self.first_result {
+            write!(self.out, ",")?;
+        }
+        self.first_result = false;
+
+        let (side, line_number, revision, path) = match line.side() {
+            ContentType::Remove => ("removed", line.old_line(), &self.old_rev, &self.old_path),
+            _ => ("added", line.new_line(), &self.new_rev, &self.new_path),
+        };
+        let text = line.text();
+        // SARIF columns count characters from 1, the end column is exclusive
+        let column = |byte: usize| text[..byte].chars().count() + 1;
+        let (start_column, end_column, pattern) = match span {
+            Some(span) => (column(span.start), column(span.end), span.pattern),
+            None => (1, column(text.len()), 0),
+        };
+        let short_hash = &self.hash[..self.hash.len().min(12)];
+        let message = format!(
+            "\"{}\" {side} in {short_hash}",
+            self.patterns.get(pattern).map_or("", String::as_str)
+        );
+
+        let result = SarifResult {
+            rule_id: rule_id(pattern),
+            rule_index: pattern,
+            level: "warning",
+            message: Message { text: &message },
+            locations: [Location {
+                physical_location: PhysicalLocation {
+                    artifact_location: ArtifactLocation { uri: path },
+                    region: Region {
+                        start_line: line_number.unwrap_or(1),
+                        start_column,
+                        end_column,
+                        snippet: Message { text },
+                    },
+                },
+            }],
+            properties: Properties {
+                commit: &self.hash,
+                parent: &self.parent,
+                revision: revision.as_deref(),
+                side,
+                date: &self.date,
+                message: &self.message,
+                notes: &self.notes,
+            },
+        };
+        serde_json::to_writer(&mut self.out, &result)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for SarifSink<W> {
+    fn begin(&mut self, patterns: &[String]) -> io::Result<()> {
+        self.patterns = patterns.to_vec();
+        let driver = Driver {
+            name: "git_grep",
+            version: env!("CARGO_PKG_VERSION"),
+            rules: patterns
+                .iter()
+                .enumerate()
+                .map(|(i, pattern)| Rule {
+                    id: rule_id(i),
+                    name: format!("pattern{i}"),
+                    short_description: Message { text: pattern },
+                })
+                .collect(),
+        };
+        write!(
+            self.out,
+            "{{\"$schema\":\"{SCHEMA}\",\"version\":\"2.1.0\",\"runs\":[{{\"tool\":{{\"driver\":"
+        )?;
+        serde_json::to_writer(&mut self.out, &driver)?;
+        write!(
+            self.out,
+            "}},\"columnKind\":\"unicodeCodePoints\",\"results\":["
+        )
+    }
+
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()> {
+        self.hash = commit.name().to_string();
+        self.parent = commit.parent_hash().to_string();
+        self.old_rev = commit.revision(&ContentType::Remove).map(str::to_string);
+        self.new_rev = commit.revision(&ContentType::Add).map(str::to_string);
+        self.date.clone_from(&commit.commit().date);
+        self.message.clone_from(&commit.commit().message);
+        self.notes = commit.commit().notes();
+        Ok(())
+    }
+
+    fn file(&mut self, file: &FileMatches) -> io::Result<()> {
+        self.old_path = strip_prefix(file.file_a(), "a/").to_string();
+        self.new_path = strip_prefix(file.file_b(), "b/").to_string();
+        Ok(())
+    }
+
+    // a result per span, so every result has the rule of its pattern
+    fn match_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        if line.spans().is_empty() {
+            return self.result(line, None);
+        }
+        for span in line.spans() {
+            self.result(line, Some(span))?;
+        }
+        Ok(())
+    }
+
+    fn context_line(&mut self, _line: &DiffLine) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end_commit(&mut self, _commit: &CommitMatcher) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn end(&mut self, _summary: &Summary) -> io::Result<()> {
+        writeln!(self.out, "]}}]}}")?;
+        self.out.flush()
+    }
+}
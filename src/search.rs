@@ -0,0 +1,726 @@
+use std::{
+    collections::HashMap,
+    io::{BufReader, Read},
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    attributes::Attributes,
+    cache::DiffCache,
+    git::git,
+    index::{index_path, HistoryIndex},
+    matcher::{for_each_commit_match, CommitMatcher, MatcherOutput},
+    paths::PathFilter,
+    pattern::{matcher_for, Literal, Matcher},
+    pretty_medium::{
+        parser::{Annotation, AnnotationKind, Commit, Uncommitted},
+        reader::CommitReader,
+    },
+    sink::{visit_commit, Sink, Summary},
+    ColorSettings, Options, Sources,
+};
+
+const DEFAULT_CACHE_SIZE: u64 = 512 * 1024 * 1024;
+
+// what the log parser reads: the full header, the parents and the notes of
+// every notes ref
+const LOG_FORMAT: [&str; 3] = ["--pretty=fuller", "--parents", "--notes=*"];
+
+/// Searches the added and removed lines of every commit in a repository's
+/// history.
+///
+/// Every commit is diffed against its first parent, and a line matches when
+/// it contains any of the patterns.
+///
+/// ```no_run
+/// use rust_aliaser::HistorySearch;
+///
+/// let output = HistorySearch::new("println!")
+///     .repo("path/to/repo")
+///     .revision("v1.0..HEAD")
+///     .path("src/")
+///     .context(2)
+///     .run()?;
+///
+/// for commit_match in output.commit_matches() {
+///     for file_match in commit_match.file_matches() {
+///         println!(
+///             "{} {}: {} matches",
+///             commit_match.commit().hash,
+///             file_match.file_b(),
+///             file_match.matched_lines()
+///         );
+///     }
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct HistorySearch {
+    options: Options,
+    revisions: Vec<String>,
+    // `--all`, `--branches` and the like, kept apart from the revisions so
+    // those are never read as options
+    refs: Vec<String>,
+    since_merge_base: Option<String>,
+    combined: bool,
+    worktree: bool,
+    staged: bool,
+    stashes: bool,
+    reflog: bool,
+    unreachable: bool,
+    greps: Vec<String>,
+    authors: Vec<String>,
+    committers: Vec<String>,
+    since: Option<String>,
+    until: Option<String>,
+    max_count: Option<usize>,
+    use_cache: bool,
+    cache_size: u64,
+    use_index: bool,
+    use_attributes: bool,
+    regex: bool,
+    matcher: Option<Arc<dyn Matcher>>,
+    globs: Vec<String>,
+    types: Vec<String>,
+    types_not: Vec<String>,
+}
+
+impl HistorySearch {
+    /// A search for `pattern` in the history of HEAD of the current directory.
+    pub fn new(pattern: impl Into<String>) -> Self {
+        let pattern = pattern.into();
+        Self {
+            options: Options {
+                before_context: 0,
+                after_context: 0,
+                show_empty: false,
+                matcher: Arc::new(Literal(pattern.clone())),
+                sources: Sources::default(),
+                patterns: vec![pattern],
+                skip_file_print: false,
+                target_dir: None,
+                color: ColorSettings::Uncolored,
+                cache: None,
+                candidates: None,
+                pathspecs: Vec::new(),
+                path_filter: None,
+                attributes: None,
+                max_file_size: None,
+                max_hunk_lines: None,
+            },
+            revisions: Vec::new(),
+            refs: Vec::new(),
+            since_merge_base: None,
+            combined: false,
+            worktree: false,
+            staged: false,
+            stashes: false,
+            reflog: false,
+            unreachable: false,
+            greps: Vec::new(),
+            authors: Vec::new(),
+            committers: Vec::new(),
+            since: None,
+            until: None,
+            max_count: None,
+            use_cache: true,
+            cache_size: DEFAULT_CACHE_SIZE,
+            use_index: true,
+            use_attributes: true,
+            regex: false,
+            matcher: None,
+            globs: Vec::new(),
+            types: Vec::new(),
+            types_not: Vec::new(),
+        }
+    }
+
+    /// The repository to search, defaults to the current directory.
+    pub fn repo(mut self, path: impl Into<String>) -> Self {
+        self.options.target_dir = Some(path.into());
+        self
+    }
+
+    /// Also match lines containing `pattern`.
+    pub fn pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.options.patterns.push(pattern.into());
+        self
+    }
+
+    /// Treat the patterns as regular expressions instead of literals.
+    pub fn regex(mut self, regex: bool) -> Self {
+        self.regex = regex;
+        self
+    }
+
+    /// Decide what matches with `matcher` instead of the patterns, which are
+    /// then only used as a description of the search.
+    pub fn matcher(mut self, matcher: impl Matcher + 'static) -> Self {
+        self.matcher = Some(Arc::new(matcher));
+        self
+    }
+
+    /// What the patterns are matched against, only the diffs by default.
+    /// Matches in a commit message count towards the commit's matches.
+    pub fn sources(mut self, sources: Sources) -> Self {
+        self.options.sources = sources;
+        self
+    }
+
+    /// A revision or range passed to `git log`, e.g. `v1.2..v1.3`, or a
+    /// revision to exclude along with its history, e.g. `^origin/main`.
+    /// Without any, the history of HEAD is searched.
+    pub fn revision(mut self, revision: impl Into<String>) -> Self {
+        self.revisions.push(revision.into());
+        self
+    }
+
+    /// Also search the history of every branch, or of the branches matching
+    /// `glob`, like `git log --branches`.
+    pub fn branches(self, glob: Option<&str>) -> Self {
+        self.refs("--branches", glob)
+    }
+
+    /// Also search the history of every tag, or of the tags matching `glob`.
+    pub fn tags(self, glob: Option<&str>) -> Self {
+        self.refs("--tags", glob)
+    }
+
+    /// Also search the history of every remote-tracking branch, or of those
+    /// matching `glob`.
+    pub fn remotes(self, glob: Option<&str>) -> Self {
+        self.refs("--remotes", glob)
+    }
+
+    /// Only search the commits of HEAD that aren't reachable from the merge
+    /// base of HEAD and `branch`, i.e. what the current branch adds to it.
+    pub fn since_merge_base(mut self, branch: impl Into<String>) -> Self {
+        self.since_merge_base = Some(branch.into());
+        self
+    }
+
+    /// With `since_merge_base`, also search the net diff between the merge
+    /// base and HEAD, as a commit labelled `<branch>...HEAD` before the others.
+    pub fn combined(mut self, combined: bool) -> Self {
+        self.combined = combined;
+        self
+    }
+
+    /// Also search the changes in the worktree that aren't staged, as
+    /// `git diff` shows them, before any commit.
+    pub fn worktree(mut self, worktree: bool) -> Self {
+        self.worktree = worktree;
+        self
+    }
+
+    /// Also search the changes staged for the next commit, as
+    /// `git diff --cached` shows them, before any commit.
+    pub fn staged(mut self, staged: bool) -> Self {
+        self.staged = staged;
+        self
+    }
+
+    /// Also search every stash entry, labelled `stash@{n}` and diffed against
+    /// the commit it was made on. Untracked files stashed with it are searched
+    /// as `stash@{n}^3`.
+    pub fn stashes(mut self, stashes: bool) -> Self {
+        self.stashes = stashes;
+        self
+    }
+
+    /// Also search the commits that are only in reflogs, no longer reachable
+    /// from any ref, labelled with a reflog entry that points to them like
+    /// `HEAD@{2}`. Their ancestors that no ref reaches are labelled relative to
+    /// it, like `HEAD@{2}~1`.
+    pub fn reflog(mut self, reflog: bool) -> Self {
+        self.reflog = reflog;
+        self
+    }
+
+    /// Also search the commits that nothing reaches, not even a reflog,
+    /// labelled `dangling`.
+    pub fn unreachable(mut self, unreachable: bool) -> Self {
+        self.unreachable = unreachable;
+        self
+    }
+
+    /// Search the history of every ref and of HEAD, like `git log --all`.
+    pub fn all(mut self) -> Self {
+        self.refs.push("--all".to_string());
+        self
+    }
+
+    fn refs(mut self, option: &str, glob: Option<&str>) -> Self {
+        self.refs.push(match glob {
+            Some(glob) => format!("{option}={glob}"),
+            None => option.to_string(),
+        });
+        self
+    }
+
+    /// Only search files matching the pathspec. Commits that don't touch a
+    /// matching file are never diffed.
+    pub fn path(mut self, pathspec: impl Into<String>) -> Self {
+        self.options.pathspecs.push(pathspec.into());
+        self
+    }
+
+    /// Only search files matching the glob, or not matching it if it starts
+    /// with `!`. A glob without a `/` matches file names in any directory.
+    pub fn glob(mut self, glob: impl Into<String>) -> Self {
+        self.globs.push(glob.into());
+        self
+    }
+
+    /// Only search files of a type such as `rust` or `js`, see `paths::TYPES`.
+    pub fn file_type(mut self, name: impl Into<String>) -> Self {
+        self.types.push(name.into());
+        self
+    }
+
+    /// Don't search files of a type such as `rust` or `js`.
+    pub fn file_type_not(mut self, name: impl Into<String>) -> Self {
+        self.types_not.push(name.into());
+        self
+    }
+
+    /// Skip files that `.gitattributes` marks as `linguist-generated`,
+    /// `linguist-vendored`, `-diff` or `binary` in the searched revision, on
+    /// by default.
+    pub fn attributes(mut self, use_attributes: bool) -> Self {
+        self.use_attributes = use_attributes;
+        self
+    }
+
+    /// Skip files whose diff is larger than `bytes`, with a note on stderr.
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.options.max_file_size = Some(bytes);
+        self
+    }
+
+    /// Skip chunks with more than `lines` lines, with a note on stderr.
+    pub fn max_hunk_lines(mut self, lines: usize) -> Self {
+        self.options.max_hunk_lines = Some(lines);
+        self
+    }
+
+    /// Only search commits whose message matches the regular expression, as
+    /// with `git log --grep`. Commits matching any of them are searched.
+    pub fn grep(mut self, pattern: impl Into<String>) -> Self {
+        self.greps.push(pattern.into());
+        self
+    }
+
+    /// Only search commits whose author matches the regular expression, as
+    /// with `git log --author`. Commits matching any of them are searched.
+    pub fn author(mut self, pattern: impl Into<String>) -> Self {
+        self.authors.push(pattern.into());
+        self
+    }
+
+    /// Only search commits whose committer matches the regular expression.
+    pub fn committer(mut self, pattern: impl Into<String>) -> Self {
+        self.committers.push(pattern.into());
+        self
+    }
+
+    /// Only search commits committed after `date`, anything `git log --since`
+    /// understands, e.g. `2024-01-01` or `2 weeks ago`.
+    pub fn since(mut self, date: impl Into<String>) -> Self {
+        self.since = Some(date.into());
+        self
+    }
+
+    /// Only search commits committed before `date`.
+    pub fn until(mut self, date: impl Into<String>) -> Self {
+        self.until = Some(date.into());
+        self
+    }
+
+    /// Only search the `n` most recent commits.
+    pub fn max_count(mut self, n: usize) -> Self {
+        self.max_count = Some(n);
+        self
+    }
+
+    /// Lines of context before and after each match.
+    pub fn context(self, lines: usize) -> Self {
+        self.before_context(lines).after_context(lines)
+    }
+
+    pub fn before_context(mut self, lines: usize) -> Self {
+        self.options.before_context = lines;
+        self
+    }
+
+    pub fn after_context(mut self, lines: usize) -> Self {
+        self.options.after_context = lines;
+        self
+    }
+
+    /// Print commits and files without matches.
+    pub fn show_empty(mut self, show_empty: bool) -> Self {
+        self.options.show_empty = show_empty;
+        self
+    }
+
+    /// Don't print the file name and number of matches per file.
+    pub fn skip_file_print(mut self, skip: bool) -> Self {
+        self.options.skip_file_print = skip;
+        self
+    }
+
+    /// Colors used for the rendered matches, uncolored by default.
+    pub fn color(mut self, color: ColorSettings) -> Self {
+        self.options.color = color;
+        self
+    }
+
+    /// Read and write the diff cache in `.git/git_grep`, on by default.
+    pub fn cache(mut self, use_cache: bool) -> Self {
+        self.use_cache = use_cache;
+        self
+    }
+
+    /// Maximum size of the diff cache in bytes.
+    pub fn cache_size(mut self, bytes: u64) -> Self {
+        self.cache_size = bytes;
+        self
+    }
+
+    /// Skip commits ruled out by the index built with `git_grep index`, on by
+    /// default. Searches limited to some paths don't use the index.
+    pub fn index(mut self, use_index: bool) -> Self {
+        self.use_index = use_index;
+        self
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Runs the search and collects every searched commit.
+    pub fn run(&self) -> Result<MatcherOutput> {
+        let mut commit_matches = Vec::new();
+        self.for_each_commit_match(|commit_match| {
+            commit_matches.push(commit_match);
+            Ok(())
+        })?;
+        Ok(MatcherOutput::new(
+            self.options.patterns.clone(),
+            commit_matches,
+        ))
+    }
+
+    /// Runs the search and sends every commit to `sink` as soon as it and all
+    /// commits before it have been searched.
+    pub fn stream(&self, sink: &mut dyn Sink) -> Result<Summary> {
+        let mut summary = Summary {
+            patterns: self.options.patterns.clone(),
+            ..Summary::default()
+        };
+        sink.begin(&summary.patterns)?;
+        self.for_each_commit_match(|commit_match| {
+            summary.commits += 1;
+            summary.total_matches += commit_match.total_matches();
+            visit_commit(&commit_match, &self.options, sink)?;
+            Ok(())
+        })?;
+        sink.end(&summary)?;
+        Ok(summary)
+    }
+
+    fn for_each_commit_match(&self, f: impl FnMut(CommitMatcher) -> Result<()>) -> Result<()> {
+        let mut options = self.options.clone();
+        options.matcher = match &self.matcher {
+            Some(matcher) => matcher.clone(),
+            None => matcher_for(&options.patterns, self.regex)?.into(),
+        };
+        if !(self.globs.is_empty() && self.types.is_empty() && self.types_not.is_empty()) {
+            let filter = PathFilter::new(&self.globs, &self.types, &self.types_not)?;
+            filter.push_down(&mut options.pathspecs);
+            options.path_filter = Some(Arc::new(filter));
+        }
+        let target_dir = options.target_dir.as_deref();
+        if self.use_attributes {
+            options.attributes = Attributes::open(target_dir).ok().map(Arc::new);
+        }
+        if self.use_cache {
+            options.cache = DiffCache::open(target_dir, self.cache_size).ok();
+        }
+        // the index is built from whole diffs, and a pathspec can split a
+        // rename into a deletion and an addition with lines the index never saw
+        if self.use_index && options.pathspecs.is_empty() {
+            // without an index every commit is searched
+            let index = index_path(target_dir)
+                .ok()
+                .and_then(|p| HistoryIndex::load(&p).ok());
+            let literals = options.matcher.required_literals();
+            options.candidates = index
+                .zip(literals)
+                .and_then(|(i, literals)| i.candidates(&literals))
+                .map(Arc::new);
+        }
+
+        let mut revisions = self.revisions.clone();
+        let mut labelled = Vec::new();
+        let uncommitted = [
+            (self.worktree, Uncommitted::Unstaged),
+            (self.staged, Uncommitted::Staged),
+        ];
+        for (_, uncommitted) in uncommitted.into_iter().filter(|(wanted, _)| *wanted) {
+            let head = rev(target_dir, &["rev-parse", "HEAD"])?;
+            labelled.push(Commit::uncommitted(uncommitted, head));
+        }
+        if let Some(branch) = &self.since_merge_base {
+            let base = rev(
+                target_dir,
+                &["merge-base", "--end-of-options", "HEAD", branch],
+            )?;
+            revisions.push(format!("{base}..HEAD"));
+            if self.combined {
+                let head = rev(target_dir, &["rev-parse", "HEAD"])?;
+                let message = format!("the changes since the merge base with {branch}");
+                labelled.push(Commit::labelled(
+                    format!("{branch}...HEAD"),
+                    head,
+                    base,
+                    message,
+                ));
+            }
+        }
+        // tags point to commits, never to the pseudo-commits above
+        let tags = if options.sources.tags {
+            tag_annotations(target_dir)?
+        } else {
+            HashMap::new()
+        };
+        let annotate = |mut commit: Commit| {
+            if let Some(annotations) = tags.get(&commit.hash) {
+                commit.annotations.extend(annotations.iter().cloned());
+            }
+            commit
+        };
+        if self.stashes {
+            labelled.extend(self.stash_commits(target_dir)?);
+        }
+        if self.reflog {
+            labelled.extend(self.reflog_commits(target_dir)?.into_iter().map(annotate));
+        }
+        if self.unreachable {
+            labelled.extend(self.dangling_commits(target_dir)?.into_iter().map(annotate));
+        }
+
+        let mut log = Command::new("git");
+        log.arg("log").args(LOG_FORMAT);
+        if let Some(n) = self.max_count {
+            log.args(["-n", &n.to_string()]);
+        }
+        log.args(self.commit_filters())
+            .args(&self.refs)
+            .arg("--end-of-options")
+            .args(&revisions)
+            .arg("--")
+            .args(&options.pathspecs);
+        if let Some(t) = target_dir {
+            log.current_dir(t);
+        }
+        let mut log = log.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        let commits =
+            CommitReader::new(BufReader::new(log.stdout.take().expect("stdout is piped")));
+
+        let commits = labelled
+            .into_iter()
+            .map(Ok)
+            .chain(commits.map(|commit| commit.map(annotate)));
+        for_each_commit_match(commits, &options, f)?;
+
+        let mut stderr = String::new();
+        log.stderr
+            .take()
+            .expect("stderr is piped")
+            .read_to_string(&mut stderr)?;
+        if !log.wait()?.success() {
+            return Err(anyhow!("git log failed: {}", stderr.trim()));
+        }
+        if let Some(cache) = &options.cache {
+            if let Err(err) = cache.prune() {
+                eprintln!("failed to prune the cache: {err}");
+            }
+        }
+        Ok(())
+    }
+
+    // the `git log` options that choose commits by their metadata, git skips
+    // the other commits so they are never diffed
+    fn commit_filters(&self) -> Vec<String> {
+        let mut filters: Vec<String> = self.greps.iter().map(|g| format!("--grep={g}")).collect();
+        filters.extend(self.authors.iter().map(|a| format!("--author={a}")));
+        filters.extend(self.committers.iter().map(|c| format!("--committer={c}")));
+        filters.extend(self.since.iter().map(|date| format!("--since={date}")));
+        filters.extend(self.until.iter().map(|date| format!("--until={date}")));
+        filters
+    }
+
+    // every stash entry that passes the filters, newest first. Pathspecs are
+    // left to the diffs, git would only check them against the first parent.
+    fn stash_commits(&self, target_dir: Option<&str>) -> Result<Vec<Commit>> {
+        let hashes = String::from_utf8(git(target_dir, &["stash", "list", "--format=%H"])?)?;
+        let hashes: Vec<&str> = hashes.lines().collect();
+        if hashes.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut revisions = vec!["--no-walk=unsorted"];
+        revisions.extend(&hashes);
+
+        let mut stashes = Vec::new();
+        for mut commit in self.log_commits(target_dir, &revisions)? {
+            let n = hashes
+                .iter()
+                .position(|h| *h == commit.hash)
+                .expect("git logs the stash entries it's given");
+            let label = format!("stash@{{{n}}}");
+            // the tree of a stash entry is the whole worktree, its second parent
+            // has the staged changes and its third, if any, the untracked files
+            let untracked = commit.parents.get(2).cloned();
+            commit.parents.truncate(1);
+            commit.label = Some(label.clone());
+            if let Some(untracked) = untracked {
+                // a root commit, diffed against the empty tree
+                let mut files = commit.clone();
+                files.hash = untracked;
+                files.parents.clear();
+                files.label = Some(format!("{label}^3"));
+                stashes.push(commit);
+                stashes.push(files);
+            } else {
+                stashes.push(commit);
+            }
+        }
+        Ok(stashes)
+    }
+
+    // the commits reflogs point to that no ref reaches, labelled with the
+    // first entry git lists for each, and their ancestors that no ref reaches,
+    // labelled relative to it like `HEAD@{1}~2`
+    fn reflog_commits(&self, target_dir: Option<&str>) -> Result<Vec<Commit>> {
+        let entries =
+            String::from_utf8(git(target_dir, &["log", "-g", "--all", "--format=%H %gd"])?)?;
+        let mut labels: HashMap<&str, String> = HashMap::new();
+        let mut revisions = vec!["rev-list", "--topo-order", "--parents"];
+        for (hash, selector) in entries.lines().filter_map(|l| l.split_once(' ')) {
+            if !labels.contains_key(hash) {
+                labels.insert(hash, selector.to_string());
+                revisions.push(hash);
+            }
+        }
+        if labels.is_empty() {
+            return Ok(Vec::new());
+        }
+        revisions.extend(["--not", "--all"]);
+
+        // children come before their parents, so every commit is labelled
+        // before its parents are
+        let unreachable = String::from_utf8(git(target_dir, &revisions)?)?;
+        let mut hashes = Vec::new();
+        for line in unreachable.lines() {
+            let mut commits = line.split(' ');
+            let Some(hash) = commits.next() else {
+                continue;
+            };
+            hashes.push(hash);
+            let label = labels[hash].clone();
+            for (i, parent) in commits.enumerate() {
+                labels
+                    .entry(parent)
+                    .or_insert_with(|| ancestor_label(&label, i));
+            }
+        }
+        if hashes.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut revisions = vec!["--no-walk=unsorted"];
+        revisions.extend(hashes);
+
+        let mut commits = self.log_commits(target_dir, &revisions)?;
+        for commit in &mut commits {
+            commit.label = labels.get(commit.hash.as_str()).cloned();
+        }
+        Ok(commits)
+    }
+
+    // the commits `git fsck` finds dangling and their ancestors that nothing
+    // else reaches, as `git fsck --lost-found` would recover them
+    fn dangling_commits(&self, target_dir: Option<&str>) -> Result<Vec<Commit>> {
+        let fsck = String::from_utf8(git(target_dir, &["fsck", "--dangling", "--no-progress"])?)?;
+        let mut revisions: Vec<&str> = fsck
+            .lines()
+            .filter_map(|l| l.strip_prefix("dangling commit "))
+            .collect();
+        if revisions.is_empty() {
+            return Ok(Vec::new());
+        }
+        revisions.extend(["--not", "--all", "--reflog"]);
+
+        let mut commits = self.log_commits(target_dir, &revisions)?;
+        for commit in &mut commits {
+            commit.label = Some("dangling".to_string());
+        }
+        Ok(commits)
+    }
+
+    // the commits `git log` lists for `revisions` that pass the filters
+    fn log_commits(&self, target_dir: Option<&str>, revisions: &[&str]) -> Result<Vec<Commit>> {
+        let filters = self.commit_filters();
+        let mut args = vec!["log"];
+        args.extend(LOG_FORMAT);
+        args.extend(filters.iter().map(String::as_str));
+        args.extend(revisions);
+        let log = git(target_dir, &args)?;
+        CommitReader::new(&log[..]).collect()
+    }
+}
+
+// how git names a parent of the commit labelled `label`, counting parents from
+// 0: the first parent of `HEAD@{1}~1` is `HEAD@{1}~2`, its second `HEAD@{1}~1^2`
+fn ancestor_label(label: &str, parent: usize) -> String {
+    if parent > 0 {
+        return format!("{label}^{}", parent + 1);
+    }
+    if let Some((base, Ok(n))) = label.rsplit_once('~').map(|(b, n)| (b, n.parse::<usize>())) {
+        return format!("{base}~{}", n + 1);
+    }
+    format!("{label}~1")
+}
+
+// the messages of the annotated tags, by the commit they point to
+fn tag_annotations(target_dir: Option<&str>) -> Result<HashMap<String, Vec<Annotation>>> {
+    let format = "--format=%(objecttype)%00%(*objecttype)%00%(*objectname)%00%(refname:short)%00\
+                  %(contents:subject)%0a%0a%(contents:body)%00";
+    let refs = String::from_utf8(git(target_dir, &["for-each-ref", format, "refs/tags"])?)?;
+    let mut tags: HashMap<String, Vec<Annotation>> = HashMap::new();
+    for tag in refs.split_terminator("\0\n") {
+        let fields: Vec<&str> = tag.split('\0').collect();
+        // lightweight tags have no message, and tags of trees or blobs no commit
+        let ["tag", "commit", commit, name, text] = fields[..] else {
+            continue;
+        };
+        tags.entry(commit.to_string())
+            .or_default()
+            .push(Annotation {
+                kind: AnnotationKind::Tag,
+                name: name.to_string(),
+                text: text.trim().to_string(),
+            });
+    }
+    Ok(tags)
+}
+
+// the single line git prints, e.g. a hash
+fn rev(target_dir: Option<&str>, args: &[&str]) -> Result<String> {
+    let out = String::from_utf8(git(target_dir, args)?)?;
+    Ok(out.trim().to_string())
+}
@@ -0,0 +1,373 @@
+use std::io::{self, Write};
+
+use crate::{
+    diff::diff_ast::ContentType,
+    formatter::{Color, StyleBuilder, Styles},
+    matcher::{CommitMatcher, DiffLine, FileMatches},
+    pattern::Span,
+    pretty_medium::parser::{Annotation, AnnotationKind},
+    ColorSettings, Options,
+};
+
+/// Totals of a finished search.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub patterns: Vec<String>,
+    /// every searched commit, including those without matches
+    pub commits: usize,
+    pub total_matches: usize,
+}
+
+/// Receives the results of a search as they are found, commit by commit in
+/// log order. Within a commit, every file is followed by its lines.
+pub trait Sink {
+    fn begin(&mut self, _patterns: &[String]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()>;
+
+    fn file(&mut self, file: &FileMatches) -> io::Result<()>;
+
+    /// Called between two blocks of lines of the same file, where lines of the
+    /// diff were left out.
+    fn block_break(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn match_line(&mut self, line: &DiffLine) -> io::Result<()>;
+
+    fn context_line(&mut self, line: &DiffLine) -> io::Result<()>;
+
+    fn end_commit(&mut self, _commit: &CommitMatcher) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end(&mut self, summary: &Summary) -> io::Result<()>;
+}
+
+/// Sends a commit and its matches to `sink`. Commits without matches are
+/// skipped unless `show_empty` is set.
+pub fn visit_commit(
+    commit: &CommitMatcher,
+    options: &Options,
+    sink: &mut dyn Sink,
+) -> io::Result<()> {
+    if !options.show_empty && commit.total_matches() == 0 {
+        return Ok(());
+    }
+    sink.begin_commit(commit)?;
+    for file in commit.file_matches() {
+        sink.file(file)?;
+        for (i, block) in file.blocks().iter().enumerate() {
+            if i > 0 {
+                sink.block_break()?;
+            }
+            for line in block {
+                if line.is_match() {
+                    sink.match_line(line)?;
+                } else {
+                    sink.context_line(line)?;
+                }
+            }
+        }
+    }
+    sink.end_commit(commit)
+}
+
+// `+    line` with the matched spans highlighted
+fn render_line(line: &DiffLine, color: &ColorSettings) -> String {
+    let style = StyleBuilder::new(color);
+    let green = style.clone().add_style(Styles::Color(Color::Green));
+    let red = style.clone().add_style(Styles::Color(Color::Red));
+    let cyan_bold = style
+        .add_style(Styles::Color(Color::Cyan))
+        .add_style(Styles::Bold);
+
+    let colorize = |s: &str| match line.side() {
+        ContentType::Add => green.build(s),
+        ContentType::Remove => red.build(s),
+        ContentType::Neutral => s.to_string(),
+    };
+    let mut out = colorize(&format!("{}    ", line.side()));
+    out.push_str(&highlight(line.text(), line.spans(), colorize, |s| {
+        cyan_bold.build(s)
+    }));
+    out
+}
+
+// ` parent hash`, or the arguments that show uncommitted changes
+fn diff_args(commit: &CommitMatcher, style: impl Fn(&str) -> String) -> String {
+    commit
+        .diff_args()
+        .into_iter()
+        .map(|arg| format!(" {}", style(arg)))
+        .collect()
+}
+
+// ` (label)` for commits that aren't in the log
+fn label(commit: &CommitMatcher) -> String {
+    match &commit.commit().label {
+        Some(label) => format!(" ({label})"),
+        None => String::new(),
+    }
+}
+
+/// `tag v1.0`, `notes` or `notes (review)`, what an annotation is shown as.
+pub fn annotation_title(annotation: &Annotation) -> String {
+    match (annotation.kind, annotation.name.as_str()) {
+        (AnnotationKind::Tag, name) => format!("tag {name}"),
+        (AnnotationKind::Note, "") => "notes".to_string(),
+        (AnnotationKind::Note, name) => format!("notes ({name})"),
+    }
+}
+
+// `text` with the spans built by `matched` and the text around them by `other`
+fn highlight(
+    text: &str,
+    spans: &[Span],
+    other: impl Fn(&str) -> String,
+    matched: impl Fn(&str) -> String,
+) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+    for span in spans {
+        out.push_str(&other(&text[pos..span.start]));
+        out.push_str(&matched(&text[span.start..span.end]));
+        pos = span.end;
+    }
+    out.push_str(&other(&text[pos..]));
+    out
+}
+
+/// The default output: a header per commit with its message and date, and the
+/// number of matches per commit and file.
+pub struct TerminalSink<W: Write> {
+    out: W,
+    color: ColorSettings,
+    skip_file_print: bool,
+    first_commit: bool,
+    first_file: bool,
+}
+
+impl<W: Write> TerminalSink<W> {
+    pub fn new(out: W, options: &Options) -> Self {
+        Self {
+            out,
+            color: options.color.clone(),
+            skip_file_print: options.skip_file_print,
+            first_commit: true,
+            first_file: true,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Sink for TerminalSink<W> {
+    fn begin(&mut self, patterns: &[String]) -> io::Result<()> {
+        let cyan_bold = StyleBuilder::new(&self.color)
+            .add_style(Styles::Color(Color::Cyan))
+            .add_style(Styles::Bold);
+        writeln!(
+            self.out,
+            "{} \"{}\"\n",
+            cyan_bold.build("Searched For:"),
+            patterns.join("\", \"")
+        )
+    }
+
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()> {
+        let cyan = StyleBuilder::new(&self.color);
+        let cyan_bold = cyan.clone().add_style(Styles::Bold);
+
+        if !self.first_commit {
+            writeln!(self.out, "~~~\n")?;
+        }
+        self.first_commit = false;
+        self.first_file = true;
+        writeln!(
+            self.out,
+            "{}{}{}",
+            cyan.build("git diff"),
+            diff_args(commit, |s| cyan_bold.build(s)),
+            label(commit),
+        )?;
+        let matched = cyan_bold.clone().add_style(Styles::Color(Color::Cyan));
+        writeln!(
+            self.out,
+            "{} {}",
+            cyan.build("message:"),
+            highlight(
+                &commit.commit().message,
+                commit.message_spans(),
+                |s| cyan_bold.build(s),
+                |s| matched.build(s)
+            ),
+        )?;
+        for (annotation, spans) in commit
+            .commit()
+            .annotations
+            .iter()
+            .zip(commit.annotation_spans())
+        {
+            writeln!(
+                self.out,
+                "{} {}",
+                cyan.build(&format!("{}:", annotation_title(annotation))),
+                highlight(
+                    &annotation.text,
+                    spans,
+                    |s| cyan_bold.build(s),
+                    |s| matched.build(s)
+                ),
+            )?;
+        }
+        writeln!(
+            self.out,
+            "{} {}",
+            cyan.build("date:"),
+            cyan_bold.build(&commit.commit().date),
+        )?;
+        writeln!(
+            self.out,
+            "{} {}\n",
+            cyan.build("Commit matches:"),
+            cyan_bold.build(&commit.total_matches().to_string()),
+        )
+    }
+
+    fn file(&mut self, file: &FileMatches) -> io::Result<()> {
+        let cyan = StyleBuilder::new(&self.color);
+        let cyan_it = cyan.clone().add_style(Styles::Italic);
+        let cyan_bold = cyan.clone().add_style(Styles::Bold);
+
+        if !self.first_file {
+            writeln!(self.out)?;
+        }
+        self.first_file = false;
+        if self.skip_file_print {
+            return Ok(());
+        }
+        writeln!(
+            self.out,
+            "{}",
+            cyan_it.build(&format!("file diff: {} {}", file.file_a(), file.file_b())),
+        )?;
+        writeln!(
+            self.out,
+            "{} {}",
+            cyan.build("File matches:"),
+            cyan_bold.build(&file.matched_lines().to_string()),
+        )
+    }
+
+    fn block_break(&mut self) -> io::Result<()> {
+        writeln!(self.out)
+    }
+
+    fn match_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        writeln!(self.out, "{}", render_line(line, &self.color))
+    }
+
+    fn context_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        writeln!(self.out, "{}", render_line(line, &self.color))
+    }
+
+    fn end_commit(&mut self, _commit: &CommitMatcher) -> io::Result<()> {
+        // show every commit as soon as it's found
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+
+    fn end(&mut self, summary: &Summary) -> io::Result<()> {
+        let cyan_bold = StyleBuilder::new(&self.color)
+            .add_style(Styles::Color(Color::Cyan))
+            .add_style(Styles::Bold);
+        writeln!(
+            self.out,
+            "{} {}",
+            cyan_bold.build("Total Matches:"),
+            summary.total_matches
+        )?;
+        self.out.flush()
+    }
+}
+
+/// Only the matched lines, with their commit and file diffs.
+pub struct SimpleSink<W: Write> {
+    out: W,
+    color: ColorSettings,
+    skip_file_print: bool,
+    first_file: bool,
+}
+
+impl<W: Write> SimpleSink<W> {
+    pub fn new(out: W, options: &Options) -> Self {
+        Self {
+            out,
+            color: options.color.clone(),
+            skip_file_print: options.skip_file_print,
+            first_file: true,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Sink for SimpleSink<W> {
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()> {
+        let cyan = StyleBuilder::new(&self.color);
+        let cyan_bold = cyan.clone().add_style(Styles::Bold);
+
+        self.first_file = true;
+        writeln!(
+            self.out,
+            "{}{}{}",
+            cyan.build("git diff"),
+            diff_args(commit, |s| cyan_bold.build(s)),
+            label(commit),
+        )
+    }
+
+    fn file(&mut self, file: &FileMatches) -> io::Result<()> {
+        if !self.first_file {
+            writeln!(self.out)?;
+        }
+        self.first_file = false;
+        if self.skip_file_print {
+            return Ok(());
+        }
+        let cyan_it = StyleBuilder::new(&self.color).add_style(Styles::Italic);
+        writeln!(
+            self.out,
+            "{}",
+            cyan_it.build(&format!("file diff: {} {}", file.file_a(), file.file_b())),
+        )
+    }
+
+    fn block_break(&mut self) -> io::Result<()> {
+        writeln!(self.out)
+    }
+
+    fn match_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        writeln!(self.out, "{}", render_line(line, &self.color))
+    }
+
+    fn context_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        writeln!(self.out, "{}", render_line(line, &self.color))
+    }
+
+    fn end_commit(&mut self, _commit: &CommitMatcher) -> io::Result<()> {
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+
+    fn end(&mut self, _summary: &Summary) -> io::Result<()> {
+        self.out.flush()
+    }
+}
@@ -0,0 +1,417 @@
+//! One line per matched line, laid out by a template like
+//! `git log --pretty=format:`.
+//!
+//! | placeholder | expands to |
+//! |---|---|
+//! | `%H`, `%h` | commit hash, full and short, or `staged` or `worktree` |
+//! | `%P`, `%p` | parent hash, full and short |
+//! | `%ad`, `%cd` | author and committer date |
+//! | `%an`, `%ae` | author name and email |
+//! | `%cn`, `%ce` | committer name and email |
+//! | `%s` | subject, the first line of the message |
+//! | `%N` | the commit's notes, on a single line |
+//! | `%f` | file, in the revision that has the line |
+//! | `%n` | line number, in the revision that has the line |
+//! | `%S` | side, `+` or `-` |
+//! | `%m` | the first matched text in the line |
+//! | `%l` | the whole line |
+//! | `%%`, `%xNN` | a `%`, the byte with hex code `NN` |
+//! | `%C(...)` | colors and styles, e.g. `%C(bold red)` |
+//! | `%C(auto)` | green for added lines, red for removed ones |
+//! | `%Creset`, `%Cred`, `%Cgreen`, ... | short forms of `%C(...)` |
+//!
+//! Colors are only written when output is colored.
+
+use std::io::{self, Write};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    diff::diff_ast::ContentType,
+    formatter::{Color, Styles},
+    matcher::{CommitMatcher, DiffLine, FileMatches},
+    paths::strip_prefix,
+    pretty_medium::parser::split_identity,
+    sink::{Sink, Summary},
+    ColorSettings, Options,
+};
+
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Hash,
+    ShortHash,
+    Parent,
+    ShortParent,
+    Date,
+    AuthorName,
+    AuthorEmail,
+    CommitterDate,
+    CommitterName,
+    CommitterEmail,
+    Subject,
+    Notes,
+    File,
+    LineNumber,
+    Side,
+    Matched,
+    Line,
+}
+
+#[derive(Debug, Clone)]
+enum Piece {
+    Literal(String),
+    Field(Field),
+    Style(Vec<Styles>),
+    // the color of the line's side
+    Auto,
+    Reset,
+}
+
+/// A parsed `--format` template.
+#[derive(Debug, Clone)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+
+        while let Some(idx) = rest.find('%') {
+            literal.push_str(&rest[..idx]);
+            rest = &rest[idx + 1..];
+
+            let (piece, len) = match rest.as_bytes() {
+                [b'%', ..] => {
+                    literal.push('%');
+                    rest = &rest[1..];
+                    continue;
+                }
+                [b'x', ..] => {
+                    let code = rest
+                        .get(1..3)
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                        .filter(u8::is_ascii)
+                        .ok_or_else(|| anyhow!("%x must be followed by an ascii hex code"))?;
+                    literal.push(code as char);
+                    rest = &rest[3..];
+                    continue;
+                }
+                [b'a', b'd', ..] => (Piece::Field(Field::Date), 2),
+                [b'a', b'n', ..] => (Piece::Field(Field::AuthorName), 2),
+                [b'a', b'e', ..] => (Piece::Field(Field::AuthorEmail), 2),
+                [b'c', b'd', ..] => (Piece::Field(Field::CommitterDate), 2),
+                [b'c', b'n', ..] => (Piece::Field(Field::CommitterName), 2),
+                [b'c', b'e', ..] => (Piece::Field(Field::CommitterEmail), 2),
+                [b'C', b'(', ..] => {
+                    let end = rest
+                        .find(')')
+                        .ok_or_else(|| anyhow!("unterminated %C( in the template"))?;
+                    (color(&rest[2..end])?, end + 1)
+                }
+                [b'C', ..] => short_color(&rest[1..])?,
+                [b'H', ..] => (Piece::Field(Field::Hash), 1),
+                [b'h', ..] => (Piece::Field(Field::ShortHash), 1),
+                [b'P', ..] => (Piece::Field(Field::Parent), 1),
+                [b'p', ..] => (Piece::Field(Field::ShortParent), 1),
+                [b's', ..] => (Piece::Field(Field::Subject), 1),
+                [b'N', ..] => (Piece::Field(Field::Notes), 1),
+                [b'f', ..] => (Piece::Field(Field::File), 1),
+                [b'n', ..] => (Piece::Field(Field::LineNumber), 1),
+                [b'S', ..] => (Piece::Field(Field::Side), 1),
+                [b'm', ..] => (Piece::Field(Field::Matched), 1),
+                [b'l', ..] => (Piece::Field(Field::Line), 1),
+                _ => {
+                    let placeholder: String = rest.chars().take(2).collect();
+                    bail!("unknown placeholder %{placeholder} in the template")
+                }
+            };
+            if !literal.is_empty() {
+                pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+            }
+            pieces.push(piece);
+            rest = &rest[len..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Ok(Self { pieces })
+    }
+}
+
+// the styles of `%C(bold red)` or `%C(bold,red)`
+fn color(spec: &str) -> Result<Piece> {
+    let mut styles = Vec::new();
+    for word in spec.split([' ', ',']).filter(|w| !w.is_empty()) {
+        styles.push(match word {
+            "auto" => return Ok(Piece::Auto),
+            "reset" => return Ok(Piece::Reset),
+            "bold" => Styles::Bold,
+            "italic" => Styles::Italic,
+            "ul" | "underline" => Styles::Underline,
+            "red" => Styles::Color(Color::Red),
+            "green" => Styles::Color(Color::Green),
+            "yellow" => Styles::Color(Color::Yellow),
+            "cyan" => Styles::Color(Color::Cyan),
+            _ => bail!("unknown color {word} in %C({spec})"),
+        });
+    }
+    Ok(Piece::Style(styles))
+}
+
+// `%Cred`, `%Cgreen` and `%Creset`, along with the length after the `C`
+fn short_color(rest: &str) -> Result<(Piece, usize)> {
+    for name in ["reset", "red", "green", "yellow", "cyan"] {
+        if rest.starts_with(name) {
+            return Ok((color(name)?, name.len() + 1));
+        }
+    }
+    bail!("%C must be followed by (...) or a color name")
+}
+
+fn short(hash: &str) -> &str {
+    // the labels of uncommitted changes aren't hashes and are kept whole
+    if !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return hash;
+    }
+    &hash[..hash.len().min(7)]
+}
+
+/// Writes a line per matched line, laid out by a `Template`.
+pub struct TemplateSink<W: Write> {
+    out: W,
+    template: Template,
+    color: ColorSettings,
+    // the commit and file the following lines belong to
+    hash: String,
+    parent: String,
+    author: String,
+    date: String,
+    committer: String,
+    committer_date: String,
+    subject: String,
+    notes: String,
+    old_path: String,
+    new_path: String,
+}
+
+impl<W: Write> TemplateSink<W> {
+    pub fn new(out: W, template: Template, options: &Options) -> Self {
+        Self {
+            out,
+            template,
+            color: options.color.clone(),
+            hash: String::new(),
+            parent: String::new(),
+            author: String::new(),
+            date: String::new(),
+            committer: String::new(),
+            committer_date: String::new(),
+            subject: String::new(),
+            notes: String::new(),
+            old_path: String::new(),
+            new_path: String::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn field(&self, field: Field, line: &DiffLine) -> String {
+        let removed = *line.side() == ContentType::Remove;
+        match field {
+            Field::Hash => self.hash.clone(),
+            Field::ShortHash => short(&self.hash).to_string(),
+            Field::Parent => self.parent.clone(),
+            Field::ShortParent => short(&self.parent).to_string(),
+            Field::Date => self.date.clone(),
+            Field::AuthorName => split_identity(&self.author).0.to_string(),
+            Field::AuthorEmail => split_identity(&self.author).1.to_string(),
+            Field::CommitterDate => self.committer_date.clone(),
+            Field::CommitterName => split_identity(&self.committer).0.to_string(),
+            Field::CommitterEmail => split_identity(&self.committer).1.to_string(),
+            Field::Subject => self.subject.clone(),
+            Field::Notes => self.notes.clone(),
+            Field::File if removed => self.old_path.clone(),
+            Field::File => self.new_path.clone(),
+            Field::LineNumber => {
+                let number = if removed {
+                    line.old_line()
+                } else {
+                    line.new_line()
+                };
+                number.map(|n| n.to_string()).unwrap_or_default()
+            }
+            Field::Side => line.side().to_string(),
+            Field::Matched => line
+                .spans()
+                .first()
+                .map(|s| line.text()[s.start..s.end].to_string())
+                .unwrap_or_default(),
+            Field::Line => line.text().to_string(),
+        }
+    }
+
+    fn render(&self, line: &DiffLine) -> String {
+        let colored = self.color == ColorSettings::Colored;
+        let mut out = String::new();
+        let mut styled = false;
+        for piece in &self.template.pieces {
+            match piece {
+                Piece::Literal(text) => out.push_str(text),
+                Piece::Field(field) => out.push_str(&self.field(*field, line)),
+                _ if !colored => {}
+                Piece::Style(styles) => {
+                    for style in styles {
+                        out.push_str(&style.to_string());
+                    }
+                    styled = true;
+                }
+                Piece::Auto => {
+                    match line.side() {
+                        ContentType::Add => out.push_str(&Color::Green.to_string()),
+                        ContentType::Remove => out.push_str(&Color::Red.to_string()),
+                        ContentType::Neutral => out.push_str(RESET),
+                    }
+                    styled = true;
+                }
+                Piece::Reset => {
+                    out.push_str(RESET);
+                    styled = false;
+                }
+            }
+        }
+        // don't let colors leak into the next line
+        if styled {
+            out.push_str(RESET);
+        }
+        out
+    }
+}
+
+impl<W: Write> Sink for TemplateSink<W> {
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()> {
+        let commit_data = commit.commit();
+        self.hash = commit.name().to_string();
+        self.parent = commit.parent_hash().to_string();
+        self.author.clone_from(&commit_data.author);
+        self.date.clone_from(&commit_data.date);
+        self.committer.clone_from(&commit_data.committer);
+        self.committer_date.clone_from(&commit_data.committer_date);
+        self.subject = commit_data.message.lines().next().unwrap_or("").to_string();
+        self.notes = commit_data.notes();
+        Ok(())
+    }
+
+    fn file(&mut self, file: &FileMatches) -> io::Result<()> {
+        self.old_path = strip_prefix(file.file_a(), "a/").to_string();
+        self.new_path = strip_prefix(file.file_b(), "b/").to_string();
+        Ok(())
+    }
+
+    fn match_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        let rendered = self.render(line);
+        writeln!(self.out, "{rendered}")
+    }
+
+    fn context_line(&mut self, _line: &DiffLine) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end_commit(&mut self, _commit: &CommitMatcher) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn end(&mut self, _summary: &Summary) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Piece, Template};
+
+    fn fields(template: &str) -> Vec<String> {
+        Template::parse(template)
+            .unwrap()
+            .pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Literal(text) => format!("{text:?}"),
+                Piece::Field(field) => format!("{field:?}"),
+                Piece::Style(styles) => format!("{styles:?}"),
+                Piece::Auto => "Auto".to_string(),
+                Piece::Reset => "Reset".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(
+            fields("%h %f:%n %l"),
+            [
+                "ShortHash",
+                "\" \"",
+                "File",
+                "\":\"",
+                "LineNumber",
+                "\" \"",
+                "Line"
+            ]
+        );
+        assert_eq!(
+            fields("%H%P%p%ad%an%ae%cd%cn%ce%s%N%S%m"),
+            [
+                "Hash",
+                "Parent",
+                "ShortParent",
+                "Date",
+                "AuthorName",
+                "AuthorEmail",
+                "CommitterDate",
+                "CommitterName",
+                "CommitterEmail",
+                "Subject",
+                "Notes",
+                "Side",
+                "Matched"
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(fields("100%% %x09done"), ["\"100% \\tdone\""]);
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(
+            fields("%C(bold red)%h%Creset %C(auto)%l%Cgreen"),
+            [
+                "[Bold, Color(Red)]",
+                "ShortHash",
+                "Reset",
+                "\" \"",
+                "Auto",
+                "Line",
+                "[Color(Green)]"
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        for template in ["%q", "%C(purple)", "%C(red", "%Cblue", "%xzz", "%x"] {
+            assert!(Template::parse(template).is_err(), "{template}");
+        }
+        assert!(Template::parse("trailing %").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+// helpers shared by the integration tests, not every test uses all of them
+#![allow(dead_code)]
+
+use std::{fs, path::Path, process::Command};
+
+use rust_aliaser::HistorySearch;
+use tempfile::TempDir;
+
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let out = Command::new("git")
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    String::from_utf8(out.stdout).unwrap().trim().to_string()
+}
+
+pub fn commit(dir: &Path, files: &[(&str, &str)], message: &str) -> String {
+    for (name, content) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    git(dir, &["add", "-A"]);
+    git(dir, &["commit", "-q", "-m", message]);
+    git(dir, &["rev-parse", "HEAD"])
+}
+
+// three commits, each touching a file under src/ and the README
+pub fn repo() -> (TempDir, Vec<String>) {
+    let dir = tempfile::tempdir().unwrap();
+    git(dir.path(), &["init", "-q"]);
+    let first = commit(
+        dir.path(),
+        &[
+            ("src/main.rs", "fn main() {\n    println!(\"hello\");\n}\n"),
+            ("README.md", "hello\n"),
+        ],
+        "first",
+    );
+    let second = commit(
+        dir.path(),
+        &[
+            (
+                "src/main.rs",
+                "fn main() {\n    println!(\"goodbye\");\n}\n",
+            ),
+            ("README.md", "goodbye\n"),
+        ],
+        "second",
+    );
+    let third = commit(
+        dir.path(),
+        &[
+            ("src/lib.rs", "pub fn greet() {}\n"),
+            ("README.md", "greet\n"),
+        ],
+        "third",
+    );
+    (dir, vec![first, second, third])
+}
+
+pub fn search(dir: &TempDir, pattern: &str) -> HistorySearch {
+    HistorySearch::new(pattern)
+        .repo(dir.path().to_str().unwrap())
+        .cache(false)
+        .index(false)
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::{commit, git, repo, search};
+use rust_aliaser::delimited::{Column, DelimitedSink};
+
+fn rows(search: rust_aliaser::HistorySearch, sink: DelimitedSink<Vec<u8>>) -> String {
+    let mut sink = sink;
+    search.stream(&mut sink).unwrap();
+    String::from_utf8(sink.into_inner()).unwrap()
+}
+
+#[test]
+fn csv_with_every_column() {
+    let (dir, commits) = repo();
+    let search = search(&dir, "goodbye").path("README.md");
+
+    let out = rows(search, DelimitedSink::csv(Vec::new()));
+
+    let mut lines = out.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "commit,parent,author,date,notes,file,old_line,new_line,side,pattern,text"
+    );
+    let row: Vec<&str> = lines.next().unwrap().split(',').collect();
+    assert_eq!(
+        row[..3],
+        [&commits[2], &commits[1], "Test <test@example.com>"]
+    );
+    assert_eq!(
+        row[4..],
+        ["", "README.md", "1", "", "-", "goodbye", "goodbye"]
+    );
+    let row: Vec<&str> = lines.next().unwrap().split(',').collect();
+    assert_eq!(row[0], commits[1]);
+    assert_eq!(
+        row[4..],
+        ["", "README.md", "", "1", "+", "goodbye", "goodbye"]
+    );
+    assert_eq!(lines.next(), None);
+}
+
+#[test]
+fn fields_are_quoted() {
+    let (dir, _) = repo();
+    let fourth = commit(
+        dir.path(),
+        &[("README.md", "say \"hi\", greet\tthem\n")],
+        "fourth",
+    );
+    let search = search(&dir, "greet").pattern("hi").max_count(1);
+    let columns = vec![Column::Commit, Column::Pattern, Column::Text];
+
+    let csv = rows(
+        search.clone(),
+        DelimitedSink::csv(Vec::new())
+            .columns(columns.clone())
+            .header(false),
+    );
+    let tsv = rows(
+        search,
+        DelimitedSink::tsv(Vec::new())
+            .columns(columns)
+            .header(false),
+    );
+
+    assert_eq!(
+        csv,
+        format!(
+            "{fourth},greet,greet\n\
+             {fourth},\"hi, greet\",\"say \"\"hi\"\", greet\tthem\"\n",
+        )
+    );
+    assert_eq!(
+        tsv,
+        format!(
+            "{fourth}\tgreet\tgreet\n\
+             {fourth}\thi, greet\t\"say \"\"hi\"\", greet\tthem\"\n",
+        )
+    );
+}
+
+#[test]
+fn uncommitted_changes_are_named_by_their_label() {
+    let (dir, _) = repo();
+    std::fs::write(dir.path().join("README.md"), "fresh staged\n").unwrap();
+    git(dir.path(), &["add", "README.md"]);
+    std::fs::write(dir.path().join("README.md"), "fresh worktree\n").unwrap();
+    let search = search(&dir, "fresh").staged(true).worktree(true);
+    let columns = vec![Column::Commit, Column::Side, Column::Text];
+
+    let csv = rows(
+        search,
+        DelimitedSink::csv(Vec::new())
+            .columns(columns)
+            .header(false),
+    );
+
+    assert_eq!(
+        csv,
+        "worktree,-,fresh staged\n\
+         worktree,+,fresh worktree\n\
+         staged,+,fresh staged\n"
+    );
+}
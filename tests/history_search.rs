@@ -0,0 +1,1031 @@
+mod common;
+
+use std::{fs, io, path::Path, process::Command};
+
+use common::{commit, git, repo, search};
+use rust_aliaser::{
+    diff::diff_ast::ContentType,
+    index::{self, HistoryIndex},
+    matcher::DiffLine,
+    pattern::Span,
+    pretty_medium::parser::AnnotationKind,
+    sink::{SimpleSink, Summary},
+    CommitMatcher, FileMatches, HistorySearch, Matcher, MatcherOutput, Sink, Sources,
+};
+
+fn matched_commits(search: HistorySearch) -> Vec<(String, usize)> {
+    search
+        .run()
+        .unwrap()
+        .commit_matches()
+        .iter()
+        .filter(|c| c.total_matches() > 0)
+        .map(|c| (c.commit().hash.clone(), c.total_matches()))
+        .collect()
+}
+
+fn expected<const N: usize>(commits: [(&String, usize); N]) -> Vec<(String, usize)> {
+    commits.map(|(hash, n)| (hash.clone(), n)).to_vec()
+}
+
+fn find<'a>(output: &'a MatcherOutput, hash: &str) -> &'a CommitMatcher {
+    output
+        .commit_matches()
+        .iter()
+        .find(|c| c.commit().hash == hash)
+        .unwrap()
+}
+
+#[test]
+fn finds_added_and_removed_lines() {
+    let (dir, commits) = repo();
+
+    let output = search(&dir, "hello").run().unwrap();
+
+    assert_eq!(output.patterns(), ["hello"]);
+    assert_eq!(output.total_matches(), 4);
+    let order = output
+        .commit_matches()
+        .iter()
+        .map(|c| &c.commit().hash)
+        .collect::<Vec<_>>();
+    assert_eq!(order, [&commits[2], &commits[1], &commits[0]]);
+    assert_eq!(find(&output, &commits[2]).total_matches(), 0);
+    assert_eq!(find(&output, &commits[0]).total_matches(), 2);
+    // the second commit removes what the first one added
+    let second = find(&output, &commits[1]);
+    assert_eq!(second.parent_hash(), commits[0]);
+    assert_eq!(second.commit().message.trim(), "second");
+
+    let files = second
+        .file_matches()
+        .iter()
+        .map(|f| (f.file_b(), f.matched_lines()))
+        .collect::<Vec<_>>();
+    assert_eq!(files, [("b/README.md", 1), ("b/src/main.rs", 1)]);
+    let lines = second.file_matches()[1].lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].text(), "    println!(\"hello\");");
+    assert_eq!(lines[0].side(), &ContentType::Remove);
+    assert_eq!(lines[0].old_line(), Some(2));
+    assert_eq!(lines[0].new_line(), None);
+    assert_eq!(
+        lines[0].spans(),
+        [Span {
+            start: 14,
+            end: 19,
+            pattern: 0
+        }]
+    );
+
+    let matches = output
+        .matches()
+        .map(|m| {
+            (
+                m.commit.commit().hash.as_str(),
+                m.file.file_b(),
+                m.line.side(),
+            )
+        })
+        .filter(|(hash, _, _)| *hash == commits[0])
+        .collect::<Vec<_>>();
+    assert_eq!(
+        matches,
+        [
+            (commits[0].as_str(), "b/README.md", &ContentType::Add),
+            (commits[0].as_str(), "b/src/main.rs", &ContentType::Add),
+        ]
+    );
+}
+
+#[test]
+fn any_of_several_patterns() {
+    let (dir, commits) = repo();
+
+    let matched = matched_commits(search(&dir, "goodbye").pattern("greet"));
+
+    assert_eq!(matched, expected([(&commits[2], 3), (&commits[1], 2)]));
+}
+
+#[test]
+fn regex_patterns() {
+    let (dir, commits) = repo();
+
+    let output = search(&dir, r"println!\(.(hello|bye)")
+        .regex(true)
+        .run()
+        .unwrap();
+
+    let spans = output
+        .matches()
+        .map(|m| (m.line.text().to_string(), m.line.spans().to_vec()))
+        .collect::<Vec<_>>();
+    let span = Span {
+        start: 4,
+        end: 19,
+        pattern: 0,
+    };
+    assert_eq!(
+        spans,
+        [
+            ("    println!(\"hello\");".to_string(), vec![span]),
+            ("    println!(\"hello\");".to_string(), vec![span]),
+        ]
+    );
+    assert_eq!(
+        matched_commits(search(&dir, "^good").regex(true)),
+        expected([(&commits[2], 1), (&commits[1], 1)])
+    );
+    assert!(search(&dir, "(").regex(true).run().is_err());
+}
+
+// matches lines that are all lowercase letters
+#[derive(Debug)]
+struct Lowercase;
+
+impl Matcher for Lowercase {
+    fn find_spans(&self, line: &str) -> Vec<Span> {
+        if !line.is_empty() && line.bytes().all(|b| b.is_ascii_lowercase()) {
+            vec![Span {
+                start: 0,
+                end: line.len(),
+                pattern: 0,
+            }]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[test]
+fn custom_matcher() {
+    let (dir, commits) = repo();
+
+    let output = search(&dir, "lowercase words")
+        .matcher(Lowercase)
+        .run()
+        .unwrap();
+
+    assert_eq!(output.patterns(), ["lowercase words"]);
+    let matches = output
+        .matches()
+        .map(|m| (m.commit.commit().hash.as_str(), m.line.text()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        matches,
+        [
+            (commits[2].as_str(), "goodbye"),
+            (commits[2].as_str(), "greet"),
+            (commits[1].as_str(), "hello"),
+            (commits[1].as_str(), "goodbye"),
+            (commits[0].as_str(), "hello"),
+        ]
+    );
+}
+
+#[test]
+fn context_lines() {
+    let (dir, commits) = repo();
+
+    let without = search(&dir, "goodbye").max_count(2).run().unwrap();
+    let with = search(&dir, "goodbye")
+        .max_count(2)
+        .context(2)
+        .run()
+        .unwrap();
+
+    let main_rs = |output: &MatcherOutput| {
+        let file = &find(output, &commits[1]).file_matches()[1];
+        assert_eq!(file.file_b(), "b/src/main.rs");
+        assert_eq!(file.matched_lines(), 1);
+        file.blocks()
+            .iter()
+            .map(|block| {
+                block
+                    .iter()
+                    .map(|l| (l.old_line(), l.new_line(), l.is_match()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(main_rs(&without), [[(None, Some(2), true)]]);
+    assert_eq!(
+        main_rs(&with),
+        [[
+            (Some(1), Some(1), false),
+            (Some(2), None, false),
+            (None, Some(2), true),
+            (Some(3), Some(3), false),
+        ]]
+    );
+}
+
+#[test]
+fn revision_range() {
+    let (dir, commits) = repo();
+
+    let range = format!("{}..{}", commits[0], commits[1]);
+    let matched = matched_commits(search(&dir, "hello").revision(range));
+
+    assert_eq!(matched, expected([(&commits[1], 2)]));
+}
+
+#[test]
+fn branches_and_exclusions() {
+    let (dir, commits) = repo();
+    let main = git(dir.path(), &["rev-parse", "--abbrev-ref", "HEAD"]);
+    git(
+        dir.path(),
+        &["checkout", "-q", "-b", "feature", &commits[1]],
+    );
+    let feature = commit(dir.path(), &[("src/feature.rs", "hello\n")], "feature");
+    git(dir.path(), &["checkout", "-q", &main]);
+    git(dir.path(), &["tag", "v1", &commits[0]]);
+
+    // commits made in the same second have no defined order across branches
+    let sorted = |search: HistorySearch| {
+        let mut matched = matched_commits(search);
+        matched.sort();
+        matched
+    };
+    let mut branches = expected([(&feature, 1), (&commits[1], 2), (&commits[0], 2)]);
+    branches.sort();
+
+    let head = matched_commits(search(&dir, "hello"));
+    let unmerged = search(&dir, "hello")
+        .revision("feature")
+        .revision(format!("^{main}"));
+
+    assert!(!head.iter().any(|(hash, _)| *hash == feature));
+    assert_eq!(sorted(search(&dir, "hello").branches(None)), branches);
+    assert_eq!(sorted(search(&dir, "hello").all()), branches);
+    assert_eq!(matched_commits(unmerged), expected([(&feature, 1)]));
+    assert_eq!(
+        matched_commits(search(&dir, "hello").tags(Some("v*"))),
+        expected([(&commits[0], 2)])
+    );
+}
+
+#[test]
+fn since_merge_base() {
+    let (dir, commits) = repo();
+    let main = git(dir.path(), &["rev-parse", "--abbrev-ref", "HEAD"]);
+    git(
+        dir.path(),
+        &["checkout", "-q", "-b", "feature", &commits[1]],
+    );
+    let added = commit(
+        dir.path(),
+        &[("src/feature.rs", "hello\nworld\n")],
+        "add feature",
+    );
+    let changed = commit(
+        dir.path(),
+        &[("src/feature.rs", "world\n")],
+        "change feature",
+    );
+
+    let feature_only = search(&dir, "hello").since_merge_base(&main);
+    assert_eq!(
+        matched_commits(feature_only),
+        expected([(&changed, 1), (&added, 1)])
+    );
+
+    // the line added and removed again isn't in the net diff
+    let output = search(&dir, "hello")
+        .pattern("world")
+        .since_merge_base(&main)
+        .combined(true)
+        .run()
+        .unwrap();
+    let combined = &output.commit_matches()[0];
+    assert_eq!(
+        combined.commit().label.as_deref(),
+        Some(&*format!("{main}...HEAD"))
+    );
+    assert_eq!(combined.commit().hash, changed);
+    assert_eq!(combined.parent_hash(), commits[1]);
+    assert_eq!(combined.total_matches(), 1);
+    assert_eq!(
+        combined.file_matches()[0].lines().next().unwrap().text(),
+        "world"
+    );
+    assert_eq!(output.commit_matches().len(), 3);
+
+    assert!(search(&dir, "hello")
+        .since_merge_base("no-such-branch")
+        .run()
+        .is_err());
+    // a branch is never read as an option
+    assert!(search(&dir, "hello")
+        .since_merge_base("--independent")
+        .run()
+        .is_err());
+}
+
+#[test]
+fn uncommitted_changes() {
+    let (dir, commits) = repo();
+    fs::write(
+        dir.path().join("src/lib.rs"),
+        "pub fn greet() {}\npub fn hello() {}\n",
+    )
+    .unwrap();
+    git(dir.path(), &["add", "src/lib.rs"]);
+    fs::write(dir.path().join("README.md"), "hello\n").unwrap();
+
+    let run = || {
+        search(&dir, "hello")
+            .cache(true)
+            .worktree(true)
+            .staged(true)
+            .run()
+            .unwrap()
+    };
+    let output = run();
+    let found: Vec<_> = output
+        .commit_matches()
+        .iter()
+        .map(|c| {
+            (
+                c.commit().label.clone(),
+                c.diff_args().join(" "),
+                c.total_matches(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        found[..2],
+        [
+            (Some("worktree".to_string()), String::new(), 1),
+            (Some("staged".to_string()), "--cached".to_string(), 1),
+        ]
+    );
+    let worktree = &output.commit_matches()[0];
+    assert_eq!(worktree.commit().hash, commits[2]);
+    let sides: Vec<_> = worktree.file_matches()[0]
+        .lines()
+        .map(|l| (l.side().clone(), l.text().to_string()))
+        .collect();
+    assert_eq!(sides, [(ContentType::Add, "hello".to_string())]);
+    assert_eq!(output.total_matches(), 1 + 1 + 2 + 2);
+
+    // the worktree changes between searches, so its diff isn't cached
+    fs::write(dir.path().join("README.md"), "greet\n").unwrap();
+    assert_eq!(run().commit_matches()[0].total_matches(), 0);
+}
+
+#[test]
+fn stashes() {
+    let (dir, commits) = repo();
+    fs::write(dir.path().join("README.md"), "hello stashed\n").unwrap();
+    git(dir.path(), &["stash", "-q"]);
+    fs::write(dir.path().join("src/lib.rs"), "pub fn hello() {}\n").unwrap();
+    git(dir.path(), &["add", "src/lib.rs"]);
+    fs::write(dir.path().join("notes.txt"), "hello untracked\n").unwrap();
+    git(
+        dir.path(),
+        &[
+            "stash",
+            "push",
+            "-q",
+            "--include-untracked",
+            "-m",
+            "wip hello",
+        ],
+    );
+
+    let output = search(&dir, "hello").stashes(true).run().unwrap();
+    let found: Vec<_> = output.commit_matches()[..3]
+        .iter()
+        .map(|c| {
+            (
+                c.commit().label.clone().unwrap(),
+                c.file_matches()[0].file_b().to_string(),
+                c.total_matches(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("stash@{0}".to_string(), "b/src/lib.rs".to_string(), 1),
+            ("stash@{0}^3".to_string(), "b/notes.txt".to_string(), 1),
+            ("stash@{1}".to_string(), "b/README.md".to_string(), 1),
+        ]
+    );
+    // every entry is diffed against the commit it was made on
+    let stash = &output.commit_matches()[0];
+    assert_eq!(stash.parent_hash(), commits[2]);
+    assert!(stash.commit().message.contains("wip hello"));
+    assert_eq!(output.commit_matches().len(), 3 + 3);
+
+    let filtered = search(&dir, "hello")
+        .stashes(true)
+        .grep("wip")
+        .run()
+        .unwrap();
+    assert_eq!(filtered.commit_matches().len(), 2);
+}
+
+#[test]
+fn reflog_and_dangling_commits() {
+    let (dir, commits) = repo();
+    git(dir.path(), &["reset", "-q", "--hard", "HEAD~1"]);
+
+    let labels = |search: HistorySearch| -> Vec<(String, Option<String>)> {
+        search
+            .run()
+            .unwrap()
+            .commit_matches()
+            .iter()
+            .filter(|c| c.total_matches() > 0)
+            .map(|c| (c.commit().hash.clone(), c.commit().label.clone()))
+            .collect()
+    };
+    assert!(labels(search(&dir, "greet")).is_empty());
+    let found = labels(search(&dir, "greet").reflog(true));
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, commits[2]);
+    assert!(found[0].1.as_ref().unwrap().ends_with("@{1}"));
+    // reflog entries are still reachable, so they aren't dangling
+    assert!(labels(search(&dir, "greet").unreachable(true)).is_empty());
+
+    git(dir.path(), &["checkout", "-q", "--detach"]);
+    let detached = commit(
+        dir.path(),
+        &[("src/lib.rs", "pub fn greet() {}\n")],
+        "detached",
+    );
+    git(dir.path(), &["checkout", "-q", "-"]);
+    git(dir.path(), &["reflog", "expire", "--expire=now", "--all"]);
+
+    // both are as old as each other, so git may list them in any order
+    let mut found = labels(search(&dir, "greet").unreachable(true));
+    found.sort();
+    let dangling = Some("dangling".to_string());
+    let mut expected = vec![(detached, dangling.clone()), (commits[2].clone(), dangling)];
+    expected.sort();
+    assert_eq!(found, expected);
+    assert!(labels(search(&dir, "greet").reflog(true)).is_empty());
+}
+
+#[test]
+fn lost_ancestors_of_reflog_commits() {
+    let (dir, commits) = repo();
+    // two commits no ref reaches, where only the child is in a reflog
+    let commit_tree = |parent: &str, message: &str| {
+        let tree = git(
+            dir.path(),
+            &["rev-parse", &format!("{}^{{tree}}", commits[1])],
+        );
+        git(
+            dir.path(),
+            &["commit-tree", "-p", parent, "-m", message, &tree],
+        )
+    };
+    let parent = commit_tree(&commits[2], "lost parent");
+    let child = commit_tree(&parent, "lost child");
+    git(dir.path(), &["checkout", "-q", "--detach", &child]);
+    git(dir.path(), &["checkout", "-q", "-"]);
+
+    let output = search(&dir, "lost")
+        .sources(Sources {
+            diff: false,
+            message: true,
+            ..Sources::default()
+        })
+        .reflog(true)
+        .run()
+        .unwrap();
+    let found: Vec<(&str, Option<&str>)> = output
+        .commit_matches()
+        .iter()
+        .filter(|c| c.total_matches() > 0)
+        .map(|c| (c.commit().hash.as_str(), c.commit().label.as_deref()))
+        .collect();
+
+    assert_eq!(
+        found,
+        [
+            (child.as_str(), Some("HEAD@{1}")),
+            (parent.as_str(), Some("HEAD@{1}~1")),
+        ]
+    );
+}
+
+#[test]
+fn tags_and_notes() {
+    let (dir, commits) = repo();
+    git(
+        dir.path(),
+        &[
+            "tag",
+            "-a",
+            "v1",
+            &commits[1],
+            "-m",
+            "Release v1\n\ngoodbye",
+        ],
+    );
+    git(dir.path(), &["tag", "light", &commits[2]]);
+    git(
+        dir.path(),
+        &["notes", "add", "-m", "reviewed, goodbye", &commits[2]],
+    );
+    git(
+        dir.path(),
+        &["notes", "--ref", "review", "add", "-m", "LGTM", &commits[2]],
+    );
+    let sources = |tags, notes| Sources {
+        diff: false,
+        message: false,
+        tags,
+        notes,
+    };
+
+    let output = search(&dir, "goodbye").run().unwrap();
+    let third = find(&output, &commits[2]);
+    let annotations: Vec<_> = third
+        .commit()
+        .annotations
+        .iter()
+        .map(|a| (a.kind, a.name.as_str(), a.text.as_str()))
+        .collect();
+    assert_eq!(
+        annotations,
+        [
+            (AnnotationKind::Note, "", "reviewed, goodbye"),
+            (AnnotationKind::Note, "review", "LGTM"),
+        ]
+    );
+    // shown, but not searched unless asked to
+    assert_eq!(third.annotation_spans(), [vec![], vec![]]);
+    assert!(find(&output, &commits[1]).commit().annotations.is_empty());
+
+    let tags = search(&dir, "goodbye").sources(sources(true, false));
+    assert_eq!(matched_commits(tags.clone()), expected([(&commits[1], 1)]));
+    let output = tags.run().unwrap();
+    let second = find(&output, &commits[1]);
+    assert_eq!(second.commit().annotations[0].name, "v1");
+    assert_eq!(
+        second.annotation_spans()[0][0].start,
+        "Release v1\n\n".len()
+    );
+
+    let notes = search(&dir, "goodbye").sources(sources(false, true));
+    assert_eq!(matched_commits(notes), expected([(&commits[2], 1)]));
+    let both = search(&dir, "goodbye").sources(sources(true, true));
+    assert_eq!(
+        matched_commits(both),
+        expected([(&commits[2], 1), (&commits[1], 1)])
+    );
+}
+
+#[test]
+fn path_filter() {
+    let (dir, commits) = repo();
+
+    let matched = matched_commits(search(&dir, "hello").path("src/"));
+
+    assert_eq!(matched, expected([(&commits[1], 1), (&commits[0], 1)]));
+}
+
+#[test]
+fn globs_and_file_types() {
+    let (dir, commits) = repo();
+
+    let rust = matched_commits(search(&dir, "hello").file_type("rust"));
+    let not_readme = matched_commits(search(&dir, "hello").glob("!README.md"));
+    let markdown = matched_commits(search(&dir, "hello").glob("*.md").path("src/"));
+
+    assert_eq!(rust, expected([(&commits[1], 1), (&commits[0], 1)]));
+    assert_eq!(not_readme, rust);
+    assert_eq!(markdown, []);
+    assert!(search(&dir, "hello").file_type("cobol").run().is_err());
+}
+
+#[test]
+fn gitattributes_and_size_limits() {
+    let dir = tempfile::tempdir().unwrap();
+    git(dir.path(), &["init", "-q"]);
+    let big = "hello\n".repeat(50);
+    commit(
+        dir.path(),
+        &[
+            (".gitattributes", "gen/** linguist-generated\n"),
+            ("gen/api.rs", "hello\n"),
+            ("src/main.rs", "hello\n"),
+            ("src/big.rs", &big),
+        ],
+        "first",
+    );
+    // unmarking a directory only applies from the commit that does it
+    commit(
+        dir.path(),
+        &[
+            ("gen/.gitattributes", "* -linguist-generated\n"),
+            ("gen/api.rs", "hello again\n"),
+        ],
+        "second",
+    );
+
+    let files = |search: HistorySearch| -> Vec<(String, String)> {
+        let output = search.run().unwrap();
+        let mut files = Vec::new();
+        for commit in output.commit_matches() {
+            let message = commit.commit().message.trim();
+            for file in commit.file_matches() {
+                files.push((message.to_string(), file.file_b().to_string()));
+            }
+        }
+        files
+    };
+    let pair = |message: &str, file: &str| (message.to_string(), file.to_string());
+
+    assert_eq!(
+        files(search(&dir, "hello")),
+        [
+            pair("second", "b/gen/api.rs"),
+            pair("first", "b/src/big.rs"),
+            pair("first", "b/src/main.rs"),
+        ]
+    );
+    assert_eq!(files(search(&dir, "hello").attributes(false)).len(), 4);
+    assert_eq!(
+        files(search(&dir, "hello").max_hunk_lines(10)),
+        [
+            pair("second", "b/gen/api.rs"),
+            pair("first", "b/src/main.rs")
+        ]
+    );
+    assert_eq!(
+        files(search(&dir, "hello").max_file_size(100)),
+        [
+            pair("second", "b/gen/api.rs"),
+            pair("first", "b/src/main.rs")
+        ]
+    );
+}
+
+#[test]
+fn deleted_files_use_the_parents_attributes() {
+    let dir = tempfile::tempdir().unwrap();
+    git(dir.path(), &["init", "-q"]);
+    commit(
+        dir.path(),
+        &[
+            ("vendor/.gitattributes", "* linguist-vendored\n"),
+            ("vendor/lib.rs", "hello\n"),
+            ("src/main.rs", "hello\n"),
+        ],
+        "first",
+    );
+    // the deleted files' .gitattributes is deleted along with them
+    git(dir.path(), &["rm", "-rq", "vendor"]);
+    git(dir.path(), &["commit", "-q", "-m", "second"]);
+
+    let messages = |search: HistorySearch| -> Vec<String> {
+        let output = search.run().unwrap();
+        output
+            .commit_matches()
+            .iter()
+            .filter(|commit| !commit.file_matches().is_empty())
+            .map(|commit| commit.commit().message.clone())
+            .collect()
+    };
+
+    assert_eq!(messages(search(&dir, "hello")), ["first"]);
+    assert_eq!(
+        messages(search(&dir, "hello").attributes(false)),
+        ["second", "first"]
+    );
+}
+
+#[test]
+fn commit_messages() {
+    let (dir, commits) = repo();
+    let messages = Sources {
+        diff: false,
+        message: true,
+        ..Sources::default()
+    };
+    let both = Sources {
+        message: true,
+        ..Sources::default()
+    };
+
+    let output = search(&dir, "ir").sources(messages).run().unwrap();
+    let first = find(&output, &commits[0]);
+    let third = find(&output, &commits[2]);
+
+    assert_eq!(output.total_matches(), 2);
+    assert_eq!(first.message_matches(), 1);
+    assert_eq!(
+        first.message_spans(),
+        [Span {
+            start: 1,
+            end: 3,
+            pattern: 0
+        }]
+    );
+    assert!(first.file_matches().is_empty());
+    assert_eq!(third.message_matches(), 1);
+    assert_eq!(
+        matched_commits(search(&dir, "greet").pattern("third").sources(both)),
+        expected([(&commits[2], 3)])
+    );
+    assert_eq!(
+        matched_commits(search(&dir, "hello").grep("^sec")),
+        expected([(&commits[1], 2)])
+    );
+}
+
+// commits a file as `author`, with `date` as the author and committer date
+fn commit_at(dir: &Path, file: &str, author: &str, date: &str) -> String {
+    fs::write(dir.join(file), format!("hello from {author}\n")).unwrap();
+    git(dir, &["add", "-A"]);
+    let status = Command::new("git")
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(["commit", "-q", "-m", file, "--author", author])
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    git(dir, &["rev-parse", "HEAD"])
+}
+
+#[test]
+fn author_committer_and_date_filters() {
+    let dir = tempfile::tempdir().unwrap();
+    git(dir.path(), &["init", "-q"]);
+    let alice = "Alice <alice@example.com>";
+    let bob = "Bob <bob@example.com>";
+    let old = commit_at(dir.path(), "a.txt", alice, "2020-01-01T12:00:00Z");
+    let middle = commit_at(dir.path(), "b.txt", bob, "2022-01-01T12:00:00Z");
+    let new = commit_at(dir.path(), "c.txt", alice, "2024-01-01T12:00:00Z");
+
+    let output = search(&dir, "hello").run().unwrap();
+    let commit = output.commit_matches()[0].commit();
+    assert_eq!(commit.author, alice);
+    assert_eq!(commit.committer, "Test <test@example.com>");
+    assert!(commit.committer_date.contains("2024"));
+
+    let alices = matched_commits(search(&dir, "hello").author("^Alice"));
+    let recent = matched_commits(search(&dir, "hello").since("2021-01-01"));
+    let window = search(&dir, "hello")
+        .since("2021-01-01")
+        .until("2023-01-01");
+
+    assert_eq!(alices, expected([(&new, 1), (&old, 1)]));
+    assert_eq!(recent, expected([(&new, 1), (&middle, 1)]));
+    assert_eq!(matched_commits(window), expected([(&middle, 1)]));
+    assert_eq!(matched_commits(search(&dir, "hello").committer("Bob")), []);
+}
+
+#[test]
+fn max_count() {
+    let (dir, commits) = repo();
+
+    let output = search(&dir, "hello").max_count(1).run().unwrap();
+
+    assert_eq!(output.commit_matches().len(), 1);
+    assert_eq!(output.commit_matches()[0].commit().hash, commits[2]);
+    assert_eq!(output.total_matches(), 0);
+}
+
+#[test]
+fn bad_revision_is_an_error() {
+    let (dir, _) = repo();
+    let output = dir.path().join("output");
+
+    assert!(search(&dir, "hello")
+        .revision("no-such-branch")
+        .run()
+        .is_err());
+    // revisions are never read as options
+    assert!(search(&dir, "hello")
+        .revision(format!("--output={}", output.display()))
+        .run()
+        .is_err());
+    assert!(!output.exists());
+}
+
+#[test]
+fn failed_diff_is_an_error() {
+    let (dir, commits) = repo();
+    // git can still log the commit, but not diff it without its tree
+    let tree = git(
+        dir.path(),
+        &["rev-parse", &format!("{}^{{tree}}", commits[1])],
+    );
+    let object = dir
+        .path()
+        .join(".git/objects")
+        .join(&tree[..2])
+        .join(&tree[2..]);
+    fs::remove_file(object).unwrap();
+
+    assert!(search(&dir, "hello").run().is_err());
+}
+
+// builds the index like `git_grep index`
+fn build_index(dir: &tempfile::TempDir) {
+    let search = search(dir, "");
+    let mut index = HistoryIndex::new();
+    index::update(&mut index, search.options()).unwrap();
+    let path = index::index_path(dir.path().to_str()).unwrap();
+    index.save(&path).unwrap();
+}
+
+#[test]
+fn indexed_results_match() {
+    let (dir, commits) = repo();
+    let long: String = (1..=10).map(|n| format!("line {n}\n")).collect();
+    let added = commit(
+        dir.path(),
+        &[("src/long.rs", &format!("{long}goodbye\n"))],
+        "long",
+    );
+    // similar enough for git to diff it as a rename
+    git(dir.path(), &["mv", "src/long.rs", "src/app.rs"]);
+    let renamed = commit(
+        dir.path(),
+        &[("src/app.rs", &format!("{long}goodbye, app\n"))],
+        "rename",
+    );
+    build_index(&dir);
+    let unindexed = commit(dir.path(), &[("README.md", "goodbye again\n")], "fourth");
+
+    let both = |search: HistorySearch| {
+        let indexed = matched_commits(search.clone().index(true));
+        assert_eq!(indexed, matched_commits(search));
+        indexed
+    };
+
+    assert_eq!(
+        both(search(&dir, "goodbye")),
+        expected([
+            (&unindexed, 1),
+            (&renamed, 2),
+            (&added, 1),
+            (&commits[2], 1),
+            (&commits[1], 2),
+        ])
+    );
+    assert_eq!(
+        both(search(&dir, "good").regex(true).pattern(r"app\b")),
+        expected([
+            (&unindexed, 1),
+            (&renamed, 2),
+            (&added, 1),
+            (&commits[2], 1),
+            (&commits[1], 2),
+        ])
+    );
+    // a pathspec that only matches one side of the rename splits it into a
+    // deletion and an addition
+    assert_eq!(
+        both(search(&dir, "goodbye").path("src/long.rs")),
+        expected([(&renamed, 1), (&added, 1)])
+    );
+    assert_eq!(
+        both(search(&dir, "goodbye").path("src/app.rs")),
+        expected([(&renamed, 1)])
+    );
+    assert_eq!(both(search(&dir, "nowhere")), Vec::new());
+
+    // commits the index rules out are never diffed, so they can't fail
+    let tree = git(
+        dir.path(),
+        &["rev-parse", &format!("{}^{{tree}}", commits[0])],
+    );
+    let object = dir
+        .path()
+        .join(".git/objects")
+        .join(&tree[..2])
+        .join(&tree[2..]);
+    fs::remove_file(object).unwrap();
+    assert!(search(&dir, "nowhere").run().is_err());
+    assert!(search(&dir, "nowhere").index(true).run().is_ok());
+    assert!(search(&dir, r"nowhere\d*")
+        .regex(true)
+        .index(true)
+        .run()
+        .is_ok());
+}
+
+#[test]
+fn cached_results_match() {
+    let (dir, _) = repo();
+    let cached = search(&dir, "hello").cache(true);
+
+    let first = cached.run().unwrap();
+    let second = cached.run().unwrap();
+
+    assert_eq!(first.total_matches(), second.total_matches());
+    assert!(dir.path().join(".git/git_grep/cache").is_dir());
+}
+
+// records every call, to check the order a sink is driven in
+#[derive(Default)]
+struct Recorder(Vec<String>);
+
+impl Sink for Recorder {
+    fn begin(&mut self, patterns: &[String]) -> io::Result<()> {
+        self.0.push(format!("begin {}", patterns.join(",")));
+        Ok(())
+    }
+
+    fn begin_commit(&mut self, commit: &CommitMatcher) -> io::Result<()> {
+        self.0
+            .push(format!("commit {}", commit.commit().message.trim()));
+        Ok(())
+    }
+
+    fn file(&mut self, file: &FileMatches) -> io::Result<()> {
+        self.0.push(format!("file {}", file.file_b()));
+        Ok(())
+    }
+
+    fn block_break(&mut self) -> io::Result<()> {
+        self.0.push("break".to_string());
+        Ok(())
+    }
+
+    fn match_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        self.0.push(format!("match {}", line.text()));
+        Ok(())
+    }
+
+    fn context_line(&mut self, line: &DiffLine) -> io::Result<()> {
+        self.0.push(format!("context {}", line.text()));
+        Ok(())
+    }
+
+    fn end_commit(&mut self, _commit: &CommitMatcher) -> io::Result<()> {
+        self.0.push("end commit".to_string());
+        Ok(())
+    }
+
+    fn end(&mut self, summary: &Summary) -> io::Result<()> {
+        self.0
+            .push(format!("end {} {}", summary.commits, summary.total_matches));
+        Ok(())
+    }
+}
+
+#[test]
+fn streams_to_a_sink_in_log_order() {
+    let (dir, _) = repo();
+    let mut recorder = Recorder::default();
+
+    let summary = search(&dir, "goodbye")
+        .context(1)
+        .stream(&mut recorder)
+        .unwrap();
+
+    assert_eq!(summary.commits, 3);
+    assert_eq!(summary.total_matches, 3);
+    assert_eq!(
+        recorder.0,
+        [
+            "begin goodbye",
+            "commit third",
+            "file b/README.md",
+            "match goodbye",
+            "context greet",
+            "end commit",
+            "commit second",
+            "file b/README.md",
+            "context hello",
+            "match goodbye",
+            "file b/src/main.rs",
+            "context     println!(\"hello\");",
+            "match     println!(\"goodbye\");",
+            "context }",
+            "end commit",
+            "end 3 3",
+        ]
+    );
+}
+
+#[test]
+fn simple_sink_output() {
+    let (dir, commits) = repo();
+    let search = search(&dir, "greet").path("src/");
+    let mut sink = SimpleSink::new(Vec::new(), search.options());
+
+    search.stream(&mut sink).unwrap();
+
+    let out = String::from_utf8(sink.into_inner()).unwrap();
+    assert_eq!(
+        out,
+        format!(
+            "git diff {} {}\nfile diff: a/src/lib.rs b/src/lib.rs\n+    pub fn greet() {{}}\n\n",
+            commits[1], commits[2]
+        )
+    );
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{repo, search};
+use rust_aliaser::html::HtmlSink;
+
+fn html(search: rust_aliaser::HistorySearch) -> String {
+    let mut sink = HtmlSink::new(Vec::new(), search.options());
+    search.stream(&mut sink).unwrap();
+    String::from_utf8(sink.into_inner()).unwrap()
+}
+
+#[test]
+fn summary_and_a_section_per_commit() {
+    let (dir, commits) = repo();
+    let page = html(search(&dir, "goodbye"));
+
+    assert!(page.starts_with("<!DOCTYPE html>"));
+    assert!(page.contains("Searched for: <code>goodbye</code>"));
+    // the totals are only known once every commit is written
+    let placeholder = page
+        .find("<span id=\"total_matches\">&hellip;</span>")
+        .unwrap();
+    let filled = page
+        .find(
+            "document.getElementById(\"commits\").textContent = \"3\";\n\
+             document.getElementById(\"total_matches\").textContent = \"3\";",
+        )
+        .unwrap();
+    let last_commit = page.rfind("</details>").unwrap();
+    assert!(placeholder < last_commit && last_commit < filled);
+    assert!(page.contains("<input id=\"filter\" type=\"search\""));
+    assert_eq!(page.matches("<details class=\"commit\"").count(), 2);
+
+    // newest first
+    let third = page
+        .find(&format!("{} {}", commits[1], commits[2]))
+        .unwrap();
+    let second = page
+        .find(&format!("{} {}", commits[0], commits[1]))
+        .unwrap();
+    assert!(third < second);
+}
+
+#[test]
+fn lines_are_escaped_and_highlighted() {
+    let (dir, _) = repo();
+    let page = html(search(&dir, "goodbye").path("src/").context(1));
+
+    assert!(page.contains("<h3>a/src/main.rs b/src/main.rs</h3>"));
+    assert!(page.contains(
+        "<span class=\"removed\">-        println!(&quot;hello&quot;);</span>\n\
+         <span class=\"added\">+        println!(&quot;<mark>goodbye</mark>&quot;);</span>\n\
+         <span class=\"context\">    }</span>\n"
+    ));
+}
+
+#[test]
+fn file_headers_can_be_skipped() {
+    let (dir, _) = repo();
+    let page = html(search(&dir, "goodbye").skip_file_print(true));
+
+    assert!(!page.contains("<h3>"));
+    assert!(page.contains("<mark>goodbye</mark>"));
+}
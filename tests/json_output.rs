@@ -0,0 +1,127 @@
+mod common;
+
+use common::{repo, search};
+use rust_aliaser::json::{JsonLinesSink, JsonSink};
+use serde_json::{json, Value};
+
+#[test]
+fn json_document() {
+    let (dir, commits) = repo();
+    let search = search(&dir, "goodbye").path("src/").context(1);
+    let mut sink = JsonSink::new(Vec::new(), search.options());
+
+    search.stream(&mut sink).unwrap();
+
+    let out: Value = serde_json::from_slice(&sink.into_inner()).unwrap();
+    let date = out["commits"][0]["date"].clone();
+    let committer_date = out["commits"][0]["committer_date"].clone();
+    assert!(date.is_string());
+    assert!(committer_date.is_string());
+    assert_eq!(
+        out,
+        json!({
+            "version": 1,
+            "patterns": ["goodbye"],
+            "commits": [{
+                "hash": commits[1],
+                "label": null,
+                "parent": commits[0],
+                "author": "Test <test@example.com>",
+                "date": date,
+                "committer": "Test <test@example.com>",
+                "committer_date": committer_date,
+                "message": "second",
+                "message_spans": [],
+                "annotations": [],
+                "total_matches": 1,
+                "files": [{
+                    "old_path": "src/main.rs",
+                    "new_path": "src/main.rs",
+                    "matched_lines": 1,
+                    "matches": [{
+                        "old_line": null,
+                        "new_line": 2,
+                        "side": "added",
+                        "text": "    println!(\"goodbye\");",
+                        "spans": [{"start": 14, "end": 21, "pattern": 0}],
+                        "context_before": [{
+                            "old_line": 2,
+                            "new_line": null,
+                            "side": "removed",
+                            "text": "    println!(\"hello\");",
+                        }],
+                        "context_after": [{
+                            "old_line": 3,
+                            "new_line": 3,
+                            "side": "context",
+                            "text": "}",
+                        }],
+                    }],
+                }],
+            }],
+            "summary": {"commits": 3, "total_matches": 1},
+        })
+    );
+}
+
+#[test]
+fn json_lines_events() {
+    let (dir, commits) = repo();
+    let search = search(&dir, "goodbye").path("README.md");
+    let mut sink = JsonLinesSink::new(Vec::new(), search.options());
+
+    search.stream(&mut sink).unwrap();
+
+    let out = String::from_utf8(sink.into_inner()).unwrap();
+    let events: Vec<Value> = out
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(
+        types,
+        ["begin", "commit", "file", "match", "commit", "file", "match", "summary"]
+    );
+
+    assert_eq!(
+        events[0],
+        json!({"type": "begin", "version": 1, "patterns": ["goodbye"]})
+    );
+    // newest first
+    assert_eq!(events[1]["hash"], commits[2]);
+    assert_eq!(events[1]["parent"], commits[1]);
+    assert_eq!(events[1]["message"], "third");
+    assert_eq!(events[1]["total_matches"], 1);
+    assert_eq!(
+        events[2],
+        json!({
+            "type": "file",
+            "hash": commits[2],
+            "old_path": "README.md",
+            "new_path": "README.md",
+            "matched_lines": 1,
+        })
+    );
+    assert_eq!(
+        events[3],
+        json!({
+            "type": "match",
+            "hash": commits[2],
+            "path": "README.md",
+            "old_line": 1,
+            "new_line": null,
+            "side": "removed",
+            "text": "goodbye",
+            "spans": [{"start": 0, "end": 7, "pattern": 0}],
+            "context_before": [],
+            "context_after": [],
+        })
+    );
+    assert_eq!(events[4]["hash"], commits[1]);
+    assert_eq!(events[6]["side"], "added");
+    assert_eq!(events[6]["new_line"], 1);
+    assert_eq!(
+        events[7],
+        json!({"type": "summary", "commits": 3, "total_matches": 2})
+    );
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::fs;
+
+use common::{commit, git, repo, search};
+use rust_aliaser::patch::PatchSink;
+
+fn numbered(lines: usize, changed: &[(usize, &str)]) -> String {
+    (1..=lines)
+        .map(|n| match changed.iter().find(|(line, _)| *line == n) {
+            Some((_, text)) => format!("{text}\n"),
+            None => format!("line {n}\n"),
+        })
+        .collect()
+}
+
+#[test]
+fn matching_chunks_apply_to_the_parent() {
+    let dir = tempfile::tempdir().unwrap();
+    git(dir.path(), &["init", "-q"]);
+    let parent = commit(
+        dir.path(),
+        &[
+            ("long.txt", &numbered(40, &[])),
+            ("gone.txt", "secret\n"),
+            ("no newline.txt", "one\ntwo"),
+        ],
+        "first",
+    );
+    commit(
+        dir.path(),
+        &[
+            ("long.txt", &numbered(40, &[(3, "three"), (30, "secret")])),
+            ("new.txt", "a secret\n"),
+            ("no newline.txt", "one\nsecret"),
+        ],
+        "second",
+    );
+    git(dir.path(), &["rm", "-q", "gone.txt"]);
+    git(dir.path(), &["commit", "-q", "--amend", "--no-edit"]);
+
+    let search = search(&dir, "secret").max_count(1);
+    let mut sink = PatchSink::new(Vec::new());
+    search.stream(&mut sink).unwrap();
+    let patch = String::from_utf8(sink.into_inner()).unwrap();
+
+    assert!(patch.starts_with("commit "));
+    assert!(patch.contains("\n    second\n"));
+    // the change to line 3 doesn't match, so the chunk with line 30 moves up
+    assert!(patch.contains("@@ -27,7 +27,7 @@"));
+    assert!(!patch.contains("three"));
+    assert!(patch.contains("deleted file mode 100644"));
+    assert!(patch.contains("new file mode 100644"));
+    assert_eq!(patch.matches("\\ No newline at end of file").count(), 2);
+
+    let patch_file = dir.path().join("matches.patch");
+    fs::write(&patch_file, &patch).unwrap();
+    git(dir.path(), &["checkout", "-q", &parent]);
+    git(
+        dir.path(),
+        &["apply", "--check", patch_file.to_str().unwrap()],
+    );
+    git(dir.path(), &["apply", patch_file.to_str().unwrap()]);
+
+    let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+    assert_eq!(read("long.txt"), numbered(40, &[(30, "secret")]));
+    assert_eq!(read("new.txt"), "a secret\n");
+    assert_eq!(read("no newline.txt"), "one\nsecret");
+    assert!(!dir.path().join("gone.txt").exists());
+}
+
+#[test]
+fn uncommitted_changes_are_named_by_their_label() {
+    let (dir, _) = repo();
+    fs::write(dir.path().join("README.md"), "fresh staged\n").unwrap();
+    git(dir.path(), &["add", "README.md"]);
+    fs::write(dir.path().join("README.md"), "fresh worktree\n").unwrap();
+
+    let search = search(&dir, "fresh").staged(true).worktree(true);
+    let mut sink = PatchSink::new(Vec::new());
+    search.stream(&mut sink).unwrap();
+    let patch = String::from_utf8(sink.into_inner()).unwrap();
+
+    let headers: Vec<&str> = patch.lines().filter(|l| l.starts_with("commit ")).collect();
+    assert_eq!(headers, ["commit worktree", "commit staged"]);
+}
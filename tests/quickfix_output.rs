@@ -0,0 +1,90 @@
+mod common;
+
+use std::fs;
+
+use common::{git, repo, search};
+use rust_aliaser::quickfix::{materialize, QuickfixSink};
+
+#[test]
+fn a_line_per_match_in_the_revision_that_has_it() {
+    let (dir, commits) = repo();
+    let search = search(&dir, "goodbye");
+    let mut sink = QuickfixSink::new(Vec::new());
+
+    search.stream(&mut sink).unwrap();
+
+    let out = String::from_utf8(sink.into_inner()).unwrap();
+    assert_eq!(
+        out,
+        format!(
+            "{second}:README.md:1:1:goodbye\n\
+             {second}:README.md:1:1:goodbye\n\
+             {second}:src/main.rs:2:15:    println!(\"goodbye\");\n",
+            second = commits[1],
+        )
+    );
+}
+
+#[test]
+fn materialized_files_have_the_matched_line() {
+    let (dir, commits) = repo();
+    let repo = dir.path().to_str();
+    let mut sink = QuickfixSink::new(Vec::new());
+    search(&dir, "hello")
+        .path("src/")
+        .stream(&mut sink)
+        .unwrap();
+    let out = String::from_utf8(sink.into_inner()).unwrap();
+    let removed = out.lines().next().unwrap();
+    assert!(removed.starts_with(&format!("{}:src/main.rs:2:", commits[0])));
+
+    let file = materialize(repo, removed).unwrap();
+
+    assert!(file.ends_with("src/main.rs"));
+    let content = fs::read_to_string(file).unwrap();
+    assert_eq!(content.lines().nth(1), Some("    println!(\"hello\");"));
+}
+
+#[test]
+fn materialize_errors() {
+    let (dir, _) = repo();
+    let repo = dir.path().to_str();
+
+    assert!(materialize(repo, "HEAD").is_err());
+    assert!(materialize(repo, "nope:README.md").is_err());
+    assert!(materialize(repo, "HEAD:../README.md").is_err());
+    assert!(materialize(repo, "HEAD:missing.md").is_err());
+    assert!(materialize(repo, "HEAD~1:README.md").is_ok());
+}
+
+#[test]
+fn uncommitted_lines_are_in_the_index_or_the_worktree() {
+    let (dir, _) = repo();
+    let repo = dir.path().to_str();
+    fs::write(dir.path().join("README.md"), "fresh staged\n").unwrap();
+    git(dir.path(), &["add", "README.md"]);
+    fs::write(dir.path().join("README.md"), "fresh worktree\n").unwrap();
+    let mut sink = QuickfixSink::new(Vec::new());
+
+    search(&dir, "fresh")
+        .staged(true)
+        .worktree(true)
+        .stream(&mut sink)
+        .unwrap();
+
+    let out = String::from_utf8(sink.into_inner()).unwrap();
+    assert_eq!(
+        out,
+        ":README.md:1:1:fresh staged\n\
+         README.md:1:1:fresh worktree\n\
+         :README.md:1:1:fresh staged\n"
+    );
+    let file = materialize(repo, out.lines().next().unwrap()).unwrap();
+    assert_eq!(fs::read_to_string(file).unwrap(), "fresh staged\n");
+    let file = materialize(repo, out.lines().nth(1).unwrap()).unwrap();
+    assert!(file.ends_with("README.md"));
+    assert_eq!(fs::read_to_string(file).unwrap(), "fresh worktree\n");
+
+    assert!(materialize(repo, "missing.md:1:1:fresh").is_err());
+    assert!(materialize(repo, "../README.md:1:1:fresh").is_err());
+}
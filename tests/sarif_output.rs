@@ -0,0 +1,140 @@
+mod common;
+
+use common::{repo, search};
+use jsonschema::JSONSchema;
+use rust_aliaser::sarif::SarifSink;
+use serde_json::{json, Value};
+
+fn sarif(search: rust_aliaser::HistorySearch) -> Value {
+    let mut sink = SarifSink::new(Vec::new());
+    search.stream(&mut sink).unwrap();
+    serde_json::from_slice(&sink.into_inner()).unwrap()
+}
+
+fn schema() -> JSONSchema {
+    let schema: Value =
+        serde_json::from_str(include_str!("fixtures/sarif-schema-2.1.0.json")).unwrap();
+    JSONSchema::compile(&schema).unwrap()
+}
+
+fn assert_valid(log: &Value) {
+    let schema = schema();
+    let errors: Vec<String> = match schema.validate(log) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors
+            .map(|e| format!("{} at {}", e, e.instance_path))
+            .collect(),
+    };
+    assert!(errors.is_empty(), "invalid SARIF: {errors:#?}");
+}
+
+#[test]
+fn validates_against_the_schema() {
+    let (dir, _) = repo();
+    let log = sarif(search(&dir, "goodbye").pattern("greet"));
+
+    assert_valid(&log);
+    assert_eq!(log["version"], "2.1.0");
+    assert_eq!(log["runs"][0]["results"].as_array().unwrap().len(), 5);
+
+    let mut broken = log.clone();
+    broken["runs"][0]["results"][0]["level"] = json!("fatal");
+    assert!(!schema().is_valid(&broken));
+}
+
+#[test]
+fn empty_search_is_valid() {
+    let (dir, _) = repo();
+    let log = sarif(search(&dir, "nothing"));
+
+    assert_valid(&log);
+    assert_eq!(log["runs"][0]["results"], json!([]));
+}
+
+#[test]
+fn a_rule_per_pattern() {
+    let (dir, _) = repo();
+    let log = sarif(search(&dir, "goodbye").pattern("greet"));
+
+    assert_eq!(
+        log["runs"][0]["tool"]["driver"]["rules"],
+        json!([
+            {"id": "pattern/0", "name": "pattern0", "shortDescription": {"text": "goodbye"}},
+            {"id": "pattern/1", "name": "pattern1", "shortDescription": {"text": "greet"}},
+        ])
+    );
+}
+
+#[test]
+fn results_are_located_in_the_revision_with_the_line() {
+    let (dir, commits) = repo();
+    let log = sarif(search(&dir, "goodbye").path("README.md"));
+    let results = log["runs"][0]["results"].as_array().unwrap();
+
+    // removed by the third commit, so it's in the second
+    assert_eq!(
+        results[0],
+        json!({
+            "ruleId": "pattern/0",
+            "ruleIndex": 0,
+            "level": "warning",
+            "message": {"text": format!("\"goodbye\" removed in {}", &commits[2][..12])},
+            "locations": [{
+                "physicalLocation": {
+                    "artifactLocation": {"uri": "README.md"},
+                    "region": {
+                        "startLine": 1,
+                        "startColumn": 1,
+                        "endColumn": 8,
+                        "snippet": {"text": "goodbye"},
+                    },
+                },
+            }],
+            "properties": {
+                "commit": commits[2],
+                "parent": commits[1],
+                "revision": commits[1],
+                "side": "removed",
+                "date": results[0]["properties"]["date"],
+                "message": "third",
+                "notes": "",
+            },
+        })
+    );
+    assert_eq!(results[1]["properties"]["side"], "added");
+    assert_eq!(results[1]["properties"]["revision"], commits[1]);
+    assert_eq!(results.len(), 2);
+}
+
+#[test]
+fn columns_count_characters() {
+    let (dir, _) = repo();
+    common::commit(dir.path(), &[("src/lib.rs", "// héllo greet\n")], "fourth");
+    let log = sarif(search(&dir, "greet").path("src/").max_count(1));
+    let region = &log["runs"][0]["results"][1]["locations"][0]["physicalLocation"]["region"];
+
+    assert_eq!(region["snippet"]["text"], "// héllo greet");
+    assert_eq!(region["startColumn"], 10);
+    assert_eq!(region["endColumn"], 15);
+}
+
+#[test]
+fn uncommitted_results_are_in_the_index_or_the_worktree() {
+    let (dir, _) = repo();
+    std::fs::write(dir.path().join("README.md"), "fresh staged\n").unwrap();
+    common::git(dir.path(), &["add", "README.md"]);
+    std::fs::write(dir.path().join("README.md"), "fresh worktree\n").unwrap();
+    let log = sarif(search(&dir, "fresh").staged(true).worktree(true));
+    let results = log["runs"][0]["results"].as_array().unwrap();
+    let properties = |i: usize| &results[i]["properties"];
+
+    assert_valid(&log);
+    assert_eq!(results.len(), 3);
+    // the worktree's removed line is in the index, its added line in the worktree
+    assert_eq!(properties(0)["revision"], "");
+    assert!(properties(1).get("revision").is_none());
+    assert_eq!(properties(2)["revision"], "");
+    // uncommitted changes have no commit of their own
+    assert_eq!(properties(0)["commit"], "worktree");
+    assert_eq!(properties(2)["commit"], "staged");
+}
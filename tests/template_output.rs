@@ -0,0 +1,81 @@
+mod common;
+
+use common::{git, repo, search};
+use rust_aliaser::{
+    template::{Template, TemplateSink},
+    ColorSettings, HistorySearch,
+};
+
+fn render(search: HistorySearch, template: &str) -> String {
+    let template = Template::parse(template).unwrap();
+    let mut sink = TemplateSink::new(Vec::new(), template, search.options());
+    search.stream(&mut sink).unwrap();
+    String::from_utf8(sink.into_inner()).unwrap()
+}
+
+#[test]
+fn a_line_per_match() {
+    let (dir, commits) = repo();
+    let out = render(
+        search(&dir, "goodbye").color(ColorSettings::Uncolored),
+        "%h %f:%n %S%l (%s by %an <%ae>, %m)",
+    );
+
+    assert_eq!(
+        out,
+        format!(
+            "{} README.md:1 -goodbye (third by Test <test@example.com>, goodbye)\n\
+             {} README.md:1 +goodbye (second by Test <test@example.com>, goodbye)\n\
+             {} src/main.rs:2 +    println!(\"goodbye\"); (second by Test <test@example.com>, goodbye)\n",
+            &commits[2][..7],
+            &commits[1][..7],
+            &commits[1][..7],
+        )
+    );
+}
+
+#[test]
+fn full_hashes_and_dates() {
+    let (dir, commits) = repo();
+    let out = render(search(&dir, "greet").path("src/"), "%H %P %ad");
+
+    let fields: Vec<&str> = out.trim_end().splitn(3, ' ').collect();
+    assert_eq!(fields[..2], [&commits[2], &commits[1]]);
+    assert!(!fields[2].is_empty());
+}
+
+#[test]
+fn colors_only_when_colored() {
+    let (dir, _) = repo();
+    let template = "%C(bold)%f%Creset %C(auto)%l";
+
+    let colored = render(
+        search(&dir, "greet")
+            .path("src/")
+            .color(ColorSettings::Colored),
+        template,
+    );
+    let uncolored = render(
+        search(&dir, "greet")
+            .path("src/")
+            .color(ColorSettings::Uncolored),
+        template,
+    );
+
+    assert_eq!(
+        colored,
+        "\x1b[1msrc/lib.rs\x1b[0m \x1b[32mpub fn greet() {}\x1b[0m\n"
+    );
+    assert_eq!(uncolored, "src/lib.rs pub fn greet() {}\n");
+}
+
+#[test]
+fn uncommitted_changes_are_named_by_their_label() {
+    let (dir, _) = repo();
+    std::fs::write(dir.path().join("README.md"), "fresh\n").unwrap();
+    git(dir.path(), &["add", "README.md"]);
+
+    let out = render(search(&dir, "fresh").staged(true), "%h %H %l");
+
+    assert_eq!(out, "staged staged fresh\n");
+}